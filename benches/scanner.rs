@@ -0,0 +1,131 @@
+//! Counts the allocations made while scanning a multi-megabyte Lox file.
+//!
+//! Run with `cargo bench --bench scanner`. The scanned tokens are then
+//! converted into the old token layout, where every lexeme was an owned
+//! `Vec<char>` and identifier and string literals were built from a clone of
+//! their characters, and the allocations of both are printed.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use crafting_interpreters::tokens::{scan_tokens, Literal, Token};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const TARGET_SIZE: usize = 4 * 1024 * 1024;
+
+const PROGRAM: &str = r#"
+// compute a few fibonacci numbers
+fun fibonacci(n) {
+    if (n <= 1) return n;
+    return fibonacci(n - 2) + fibonacci(n - 1);
+}
+
+class Greeter {
+    greet(name) {
+        print "hello " + name + ", the answer is";
+        print 42.5 * 2 != 84;
+    }
+}
+
+var greeter = Greeter();
+for (var i = 0; i < 20; i = i + 1) {
+    greeter.greet("reader number " + i);
+    print fibonacci(i) >= 100 and !false or nil;
+}
+"#;
+
+/// The token layout before lexemes were borrowed from the source.
+#[allow(dead_code)]
+struct OwnedToken {
+    lexeme: Option<Vec<char>>,
+    literal: Option<String>,
+}
+
+impl From<&Token<'_>> for OwnedToken {
+    fn from(token: &Token<'_>) -> Self {
+        // copied twice, like `String::from_iter(chars.clone())` did
+        let owned = |text: &str| {
+            let chars: Vec<char> = text.chars().collect();
+            String::from_iter(chars.clone())
+        };
+        let literal = match &token.literal {
+            Some(Literal::Identifier(name)) => Some(owned(name)),
+            Some(Literal::Str(value)) => Some(owned(value)),
+            Some(Literal::Number(_)) | None => None,
+        };
+        OwnedToken {
+            lexeme: Some(token.lexeme.chars().collect()),
+            literal,
+        }
+    }
+}
+
+/// Runs `f` and returns its result with the allocations and bytes it made.
+fn measure<T>(f: impl FnOnce() -> T) -> (T, usize, usize) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let bytes = ALLOCATED_BYTES.load(Ordering::Relaxed);
+    let result = f();
+    (
+        result,
+        ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes,
+    )
+}
+
+fn main() {
+    let source = PROGRAM.repeat(TARGET_SIZE / PROGRAM.len() + 1);
+
+    let started = Instant::now();
+    let ((tokens, _errors), borrowed_allocations, borrowed_bytes) =
+        measure(|| scan_tokens(&source));
+    let elapsed = started.elapsed();
+
+    let (owned, owned_allocations, owned_bytes) =
+        measure(|| tokens.iter().map(OwnedToken::from).collect::<Vec<_>>());
+    drop(owned);
+
+    eprintln!(
+        "scanned {} bytes into {} tokens in {:?}",
+        source.len(),
+        tokens.len(),
+        elapsed
+    );
+    eprintln!(
+        "borrowed lexemes: {} allocations ({:.2} per token), {} bytes",
+        borrowed_allocations,
+        borrowed_allocations as f64 / tokens.len() as f64,
+        borrowed_bytes
+    );
+    eprintln!(
+        "owned lexemes:    {} allocations ({:.2} per token), {} bytes",
+        borrowed_allocations + owned_allocations,
+        (borrowed_allocations + owned_allocations) as f64 / tokens.len() as f64,
+        borrowed_bytes + owned_bytes
+    );
+}
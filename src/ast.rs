@@ -0,0 +1,183 @@
+use std::borrow::Cow;
+
+use crate::tokens::{Span, Token};
+
+pub mod printer;
+pub mod visit;
+
+/// An expression as written in the source.
+///
+/// Nodes keep the tokens they were parsed from, operators and names
+/// included, so later passes can point diagnostics at them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr<'src> {
+    // a + b, a == b etc
+    Binary {
+        left: Box<Expr<'src>>,
+        operator: Token<'src>,
+        right: Box<Expr<'src>>,
+    },
+    // -a or !a
+    Unary {
+        operator: Token<'src>,
+        right: Box<Expr<'src>>,
+    },
+    // ( expression ), the span includes the parentheses
+    Grouping {
+        expression: Box<Expr<'src>>,
+        span: Span,
+    },
+    // 123, "hello", true, nil
+    Literal {
+        value: LiteralValue<'src>,
+        span: Span,
+    },
+    // a variable read
+    Variable {
+        name: Token<'src>,
+    },
+    // name = value
+    Assign {
+        name: Token<'src>,
+        value: Box<Expr<'src>>,
+    },
+    // a and b, a or b, kept apart from Binary as the right side may not run
+    Logical {
+        left: Box<Expr<'src>>,
+        operator: Token<'src>,
+        right: Box<Expr<'src>>,
+    },
+    // callee(arguments), paren is the closing ')'
+    Call {
+        callee: Box<Expr<'src>>,
+        paren: Token<'src>,
+        arguments: Vec<Expr<'src>>,
+    },
+    // object.name
+    Get {
+        object: Box<Expr<'src>>,
+        name: Token<'src>,
+    },
+    // object.name = value
+    Set {
+        object: Box<Expr<'src>>,
+        name: Token<'src>,
+        value: Box<Expr<'src>>,
+    },
+    This {
+        keyword: Token<'src>,
+    },
+    // super.method
+    Super {
+        keyword: Token<'src>,
+        method: Token<'src>,
+    },
+}
+
+/// The value of a literal expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LiteralValue<'src> {
+    Number(f64),
+    Str(Cow<'src, str>),
+    Bool(bool),
+    Nil,
+}
+
+impl Expr<'_> {
+    /// The source covered by the whole expression.
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                left.span().to(right.span())
+            }
+            Expr::Unary { operator, right } => operator.span.to(right.span()),
+            Expr::Grouping { span, .. } | Expr::Literal { span, .. } => *span,
+            Expr::Variable { name } => name.span,
+            Expr::Assign { name, value } => name.span.to(value.span()),
+            Expr::Call { callee, paren, .. } => callee.span().to(paren.span),
+            Expr::Get { object, name } => object.span().to(name.span),
+            Expr::Set { object, value, .. } => object.span().to(value.span()),
+            Expr::This { keyword } => keyword.span,
+            Expr::Super { keyword, method } => keyword.span.to(method.span),
+        }
+    }
+}
+
+/// A statement or declaration.
+///
+/// Every statement keeps the span of its whole source text, from its first
+/// token to its closing ';' or '}'.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt<'src> {
+    // an expression evaluated for its side effects ie a call
+    Expression {
+        expression: Expr<'src>,
+        span: Span,
+    },
+    Print {
+        expression: Expr<'src>,
+        span: Span,
+    },
+    // var name = initializer;
+    Var {
+        name: Token<'src>,
+        initializer: Option<Expr<'src>>,
+        span: Span,
+    },
+    // { statements }
+    Block {
+        statements: Vec<Stmt<'src>>,
+        span: Span,
+    },
+    If {
+        condition: Expr<'src>,
+        then_branch: Box<Stmt<'src>>,
+        else_branch: Option<Box<Stmt<'src>>>,
+        span: Span,
+    },
+    // for loops are desugared into a While inside a Block
+    While {
+        condition: Expr<'src>,
+        body: Box<Stmt<'src>>,
+        span: Span,
+    },
+    Function(Function<'src>),
+    Return {
+        keyword: Token<'src>,
+        value: Option<Expr<'src>>,
+        span: Span,
+    },
+    // class name < superclass { methods }, the superclass is always a Variable
+    Class {
+        name: Token<'src>,
+        superclass: Option<Expr<'src>>,
+        methods: Vec<Function<'src>>,
+        span: Span,
+    },
+}
+
+/// A function declaration or a method of a class.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function<'src> {
+    pub name: Token<'src>,
+    pub params: Vec<Token<'src>>,
+    pub body: Vec<Stmt<'src>>,
+    pub span: Span,
+}
+
+impl Stmt<'_> {
+    /// The source covered by the whole statement.
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Expression { span, .. }
+            | Stmt::Print { span, .. }
+            | Stmt::Var { span, .. }
+            | Stmt::Block { span, .. }
+            | Stmt::If { span, .. }
+            | Stmt::While { span, .. }
+            | Stmt::Return { span, .. }
+            | Stmt::Class { span, .. } => *span,
+            Stmt::Function(function) => function.span,
+        }
+    }
+}
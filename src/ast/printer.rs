@@ -0,0 +1,403 @@
+//! Renders syntax trees as text for debugging the parser, either as
+//! S-expressions, `(* (- 123) (group 45.67))`, or as an indented tree with
+//! the span of every node.
+//!
+//! Literals are written the way the scanner reads them: numbers in their
+//! shortest form that parses back to the same value and strings quoted and
+//! escaped, so `"a\tb"` prints as `"a\tb"` rather than with a raw tab.
+
+use std::fmt::Write;
+
+use crate::ast::{Expr, Function, LiteralValue, Stmt};
+use crate::tokens::Span;
+
+/// The expression as an S-expression.
+pub fn sexpr(expr: &Expr) -> String {
+    match expr {
+        Expr::Binary {
+            left,
+            operator,
+            right,
+        }
+        | Expr::Logical {
+            left,
+            operator,
+            right,
+        } => parenthesize(operator.lexeme, [sexpr(left), sexpr(right)]),
+        Expr::Unary { operator, right } => parenthesize(operator.lexeme, [sexpr(right)]),
+        Expr::Grouping { expression, .. } => parenthesize("group", [sexpr(expression)]),
+        Expr::Literal { value, .. } => literal(value),
+        Expr::Variable { name } => name.lexeme.to_string(),
+        Expr::Assign { name, value } => parenthesize("=", [name.lexeme.to_string(), sexpr(value)]),
+        Expr::Call {
+            callee, arguments, ..
+        } => parenthesize(
+            "call",
+            std::iter::once(sexpr(callee)).chain(arguments.iter().map(sexpr)),
+        ),
+        Expr::Get { object, name } => parenthesize(".", [sexpr(object), name.lexeme.to_string()]),
+        Expr::Set {
+            object,
+            name,
+            value,
+        } => parenthesize(
+            "=",
+            [
+                parenthesize(".", [sexpr(object), name.lexeme.to_string()]),
+                sexpr(value),
+            ],
+        ),
+        Expr::This { .. } => "this".to_string(),
+        Expr::Super { method, .. } => parenthesize("super", [method.lexeme.to_string()]),
+    }
+}
+
+/// The statement as an S-expression, for loops show up desugared.
+pub fn sexpr_stmt(stmt: &Stmt) -> String {
+    match stmt {
+        Stmt::Expression { expression, .. } => parenthesize(";", [sexpr(expression)]),
+        Stmt::Print { expression, .. } => parenthesize("print", [sexpr(expression)]),
+        Stmt::Var {
+            name, initializer, ..
+        } => parenthesize(
+            "var",
+            std::iter::once(name.lexeme.to_string()).chain(initializer.iter().map(sexpr)),
+        ),
+        Stmt::Block { statements, .. } => parenthesize("block", statements.iter().map(sexpr_stmt)),
+        Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } => parenthesize(
+            "if",
+            [sexpr(condition), sexpr_stmt(then_branch)]
+                .into_iter()
+                .chain(else_branch.iter().map(|branch| sexpr_stmt(branch))),
+        ),
+        Stmt::While {
+            condition, body, ..
+        } => parenthesize("while", [sexpr(condition), sexpr_stmt(body)]),
+        Stmt::Function(function) => sexpr_function(function),
+        Stmt::Return { value, .. } => parenthesize("return", value.iter().map(sexpr)),
+        Stmt::Class {
+            name,
+            superclass,
+            methods,
+            ..
+        } => {
+            let head = match superclass {
+                Some(superclass) => format!("class {} < {}", name.lexeme, sexpr(superclass)),
+                None => format!("class {}", name.lexeme),
+            };
+            parenthesize(&head, methods.iter().map(sexpr_function))
+        }
+    }
+}
+
+fn sexpr_function(function: &Function) -> String {
+    let params: Vec<&str> = function.params.iter().map(|param| param.lexeme).collect();
+    parenthesize(
+        &format!("fun {} ({})", function.name.lexeme, params.join(" ")),
+        function.body.iter().map(sexpr_stmt),
+    )
+}
+
+fn parenthesize(head: &str, items: impl IntoIterator<Item = String>) -> String {
+    let mut text = format!("({}", head);
+    for item in items {
+        text.push(' ');
+        text.push_str(&item);
+    }
+    text.push(')');
+    text
+}
+
+/// The statements as an indented tree, one node per line followed by its
+/// position, `[line:column start..end]`, children indented under it.
+pub fn tree(statements: &[Stmt]) -> String {
+    let mut out = String::new();
+    for stmt in statements {
+        write_stmt(&mut out, stmt, 0);
+    }
+    out
+}
+
+/// The expression as an indented tree, see `tree`.
+pub fn expr_tree(expr: &Expr) -> String {
+    let mut out = String::new();
+    write_expr(&mut out, expr, 0);
+    out
+}
+
+fn write_node(out: &mut String, depth: usize, label: &str, span: Span) {
+    let _ = writeln!(
+        out,
+        "{:indent$}{} [{}:{} {}..{}]",
+        "",
+        label,
+        span.line,
+        span.column,
+        span.start,
+        span.end,
+        indent = depth * 2
+    );
+}
+
+fn write_expr(out: &mut String, expr: &Expr, depth: usize) {
+    let span = expr.span();
+    match expr {
+        Expr::Binary {
+            left,
+            operator,
+            right,
+        } => {
+            write_node(out, depth, &format!("Binary {}", operator.lexeme), span);
+            write_expr(out, left, depth + 1);
+            write_expr(out, right, depth + 1);
+        }
+        Expr::Logical {
+            left,
+            operator,
+            right,
+        } => {
+            write_node(out, depth, &format!("Logical {}", operator.lexeme), span);
+            write_expr(out, left, depth + 1);
+            write_expr(out, right, depth + 1);
+        }
+        Expr::Unary { operator, right } => {
+            write_node(out, depth, &format!("Unary {}", operator.lexeme), span);
+            write_expr(out, right, depth + 1);
+        }
+        Expr::Grouping { expression, .. } => {
+            write_node(out, depth, "Grouping", span);
+            write_expr(out, expression, depth + 1);
+        }
+        Expr::Literal { value, .. } => {
+            write_node(out, depth, &format!("Literal {}", literal(value)), span)
+        }
+        Expr::Variable { name } => {
+            write_node(out, depth, &format!("Variable {}", name.lexeme), span)
+        }
+        Expr::Assign { name, value } => {
+            write_node(out, depth, &format!("Assign {}", name.lexeme), span);
+            write_expr(out, value, depth + 1);
+        }
+        Expr::Call {
+            callee, arguments, ..
+        } => {
+            write_node(out, depth, "Call", span);
+            write_expr(out, callee, depth + 1);
+            for argument in arguments {
+                write_expr(out, argument, depth + 1);
+            }
+        }
+        Expr::Get { object, name } => {
+            write_node(out, depth, &format!("Get {}", name.lexeme), span);
+            write_expr(out, object, depth + 1);
+        }
+        Expr::Set {
+            object,
+            name,
+            value,
+        } => {
+            write_node(out, depth, &format!("Set {}", name.lexeme), span);
+            write_expr(out, object, depth + 1);
+            write_expr(out, value, depth + 1);
+        }
+        Expr::This { .. } => write_node(out, depth, "This", span),
+        Expr::Super { method, .. } => {
+            write_node(out, depth, &format!("Super {}", method.lexeme), span)
+        }
+    }
+}
+
+fn write_stmt(out: &mut String, stmt: &Stmt, depth: usize) {
+    let span = stmt.span();
+    match stmt {
+        Stmt::Expression { expression, .. } => {
+            write_node(out, depth, "Expression", span);
+            write_expr(out, expression, depth + 1);
+        }
+        Stmt::Print { expression, .. } => {
+            write_node(out, depth, "Print", span);
+            write_expr(out, expression, depth + 1);
+        }
+        Stmt::Var {
+            name, initializer, ..
+        } => {
+            write_node(out, depth, &format!("Var {}", name.lexeme), span);
+            if let Some(initializer) = initializer {
+                write_expr(out, initializer, depth + 1);
+            }
+        }
+        Stmt::Block { statements, .. } => {
+            write_node(out, depth, "Block", span);
+            for stmt in statements {
+                write_stmt(out, stmt, depth + 1);
+            }
+        }
+        Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            write_node(out, depth, "If", span);
+            write_expr(out, condition, depth + 1);
+            write_stmt(out, then_branch, depth + 1);
+            if let Some(else_branch) = else_branch {
+                write_stmt(out, else_branch, depth + 1);
+            }
+        }
+        Stmt::While {
+            condition, body, ..
+        } => {
+            write_node(out, depth, "While", span);
+            write_expr(out, condition, depth + 1);
+            write_stmt(out, body, depth + 1);
+        }
+        Stmt::Function(function) => write_function(out, function, depth),
+        Stmt::Return { value, .. } => {
+            write_node(out, depth, "Return", span);
+            if let Some(value) = value {
+                write_expr(out, value, depth + 1);
+            }
+        }
+        Stmt::Class {
+            name,
+            superclass,
+            methods,
+            ..
+        } => {
+            let label = match superclass {
+                Some(superclass) => format!("Class {} < {}", name.lexeme, sexpr(superclass)),
+                None => format!("Class {}", name.lexeme),
+            };
+            write_node(out, depth, &label, span);
+            for method in methods {
+                write_function(out, method, depth + 1);
+            }
+        }
+    }
+}
+
+fn write_function(out: &mut String, function: &Function, depth: usize) {
+    let params: Vec<&str> = function.params.iter().map(|param| param.lexeme).collect();
+    let label = format!("Function {}({})", function.name.lexeme, params.join(", "));
+    write_node(out, depth, &label, function.span);
+    for stmt in &function.body {
+        write_stmt(out, stmt, depth + 1);
+    }
+}
+
+/// The literal as Lox source.
+fn literal(value: &LiteralValue) -> String {
+    match value {
+        LiteralValue::Number(value) => value.to_string(),
+        LiteralValue::Str(value) => {
+            let mut text = String::from("\"");
+            for c in value.chars() {
+                match c {
+                    '"' => text.push_str("\\\""),
+                    '\\' => text.push_str("\\\\"),
+                    '\n' => text.push_str("\\n"),
+                    '\t' => text.push_str("\\t"),
+                    '\r' => text.push_str("\\r"),
+                    '\0' => text.push_str("\\0"),
+                    c if c.is_control() => {
+                        let _ = write!(text, "\\u{{{:x}}}", c as u32);
+                    }
+                    c => text.push(c),
+                }
+            }
+            text.push('"');
+            text
+        }
+        LiteralValue::Bool(value) => value.to_string(),
+        LiteralValue::Nil => "nil".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_parse::{parse, program};
+    use crate::tokens::scan_tokens;
+
+    fn expression(source: &str) -> Expr<'_> {
+        parse(source).unwrap()
+    }
+
+    #[test]
+    fn lisp_form() {
+        assert_eq!(
+            sexpr(&expression("-123 * (45.67)")),
+            "(* (- 123) (group 45.67))"
+        );
+        assert_eq!(
+            sexpr(&expression("a.b = c(1, nil) or !true")),
+            "(= (. a b) (or (call c 1 nil) (! true)))"
+        );
+        assert_eq!(sexpr(&expression("super.m(this)")), "(call (super m) this)");
+    }
+
+    #[test]
+    fn lisp_form_of_statements() {
+        let statements = program("class A < B { m(x) { if (x) return 1; else print x; } }");
+        assert_eq!(
+            sexpr_stmt(&statements[0]),
+            "(class A < B (fun m (x) (if x (return 1) (print x))))"
+        );
+    }
+
+    #[test]
+    fn literals_round_trip() {
+        let source = r#""tab\there" "quote\" back\\slash\n" "\u{7}bell" "é" 0.1 1e300 0x1F 2.5e-3"#;
+        let (tokens, _) = scan_tokens(source);
+        for token in &tokens[..tokens.len() - 1] {
+            let original = expression(token.lexeme);
+            let printed = sexpr(&original);
+            let reparsed = expression(&printed);
+            let (Expr::Literal { value: before, .. }, Expr::Literal { value: after, .. }) =
+                (&original, &reparsed)
+            else {
+                panic!("{} did not parse back to a literal", printed);
+            };
+            assert_eq!(before, after, "{} printed as {}", token.lexeme, printed);
+        }
+        assert_eq!(sexpr(&expression(r#""a\tb""#)), r#""a\tb""#);
+        assert_eq!(sexpr(&expression("0x1F")), "31");
+    }
+
+    #[test]
+    fn indented_tree() {
+        let source = "var a = 1 + 2;\nfun f(x) {\n  return -x;\n}";
+        assert_eq!(
+            tree(&program(source)),
+            "\
+Var a [1:1 0..14]
+  Binary + [1:9 8..13]
+    Literal 1 [1:9 8..9]
+    Literal 2 [1:13 12..13]
+Function f(x) [2:1 15..40]
+  Return [3:3 28..38]
+    Unary - [3:10 35..37]
+      Variable x [3:11 36..37]
+"
+        );
+    }
+
+    #[test]
+    fn expression_tree() {
+        assert_eq!(
+            expr_tree(&expression("f(a).b")),
+            "\
+Get b [1:1 0..6]
+  Call [1:1 0..4]
+    Variable f [1:1 0..1]
+    Variable a [1:3 2..3]
+"
+        );
+    }
+}
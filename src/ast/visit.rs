@@ -0,0 +1,698 @@
+//! Visitor traits over the syntax tree, for passes such as a resolver,
+//! linter or interpreter.
+//!
+//! There is one method per node kind, taking the node's fields. The default
+//! of each visits the node's children in source order and returns
+//! `R::default()`, so a pass only overrides the nodes it cares about. An
+//! override that still wants the children visited calls `visit_expr` or
+//! `visit_stmt` on them itself.
+//!
+//! `visit_expr` and `visit_stmt` dispatch on the node kind through
+//! `walk_expr` and `walk_stmt`, overriding them gives a pass a hook around
+//! every node, ie to replace a node after its children were rewritten.
+//!
+//! `StmtVisitor` builds on `ExprVisitor`, since statements contain
+//! expressions, and the two can return different types: an interpreter
+//! evaluating expressions to values but running statements for their
+//! effects. The `Mut` variants take the tree by mutable reference, for
+//! passes that rewrite it.
+
+use crate::ast::{Expr, Function, LiteralValue, Stmt};
+use crate::tokens::{Span, Token};
+
+pub trait ExprVisitor<'src, R: Default = ()> {
+    fn visit_expr(&mut self, expr: &Expr<'src>) -> R {
+        walk_expr(self, expr)
+    }
+
+    fn visit_binary(&mut self, left: &Expr<'src>, operator: &Token<'src>, right: &Expr<'src>) -> R {
+        let _ = operator;
+        self.visit_expr(left);
+        self.visit_expr(right);
+        R::default()
+    }
+
+    fn visit_unary(&mut self, operator: &Token<'src>, right: &Expr<'src>) -> R {
+        let _ = operator;
+        self.visit_expr(right);
+        R::default()
+    }
+
+    fn visit_grouping(&mut self, expression: &Expr<'src>, span: Span) -> R {
+        let _ = span;
+        self.visit_expr(expression);
+        R::default()
+    }
+
+    fn visit_literal(&mut self, value: &LiteralValue<'src>, span: Span) -> R {
+        let _ = (value, span);
+        R::default()
+    }
+
+    fn visit_variable(&mut self, name: &Token<'src>) -> R {
+        let _ = name;
+        R::default()
+    }
+
+    fn visit_assign(&mut self, name: &Token<'src>, value: &Expr<'src>) -> R {
+        let _ = name;
+        self.visit_expr(value);
+        R::default()
+    }
+
+    fn visit_logical(
+        &mut self,
+        left: &Expr<'src>,
+        operator: &Token<'src>,
+        right: &Expr<'src>,
+    ) -> R {
+        let _ = operator;
+        self.visit_expr(left);
+        self.visit_expr(right);
+        R::default()
+    }
+
+    fn visit_call(
+        &mut self,
+        callee: &Expr<'src>,
+        paren: &Token<'src>,
+        arguments: &[Expr<'src>],
+    ) -> R {
+        let _ = paren;
+        self.visit_expr(callee);
+        for argument in arguments {
+            self.visit_expr(argument);
+        }
+        R::default()
+    }
+
+    fn visit_get(&mut self, object: &Expr<'src>, name: &Token<'src>) -> R {
+        let _ = name;
+        self.visit_expr(object);
+        R::default()
+    }
+
+    fn visit_set(&mut self, object: &Expr<'src>, name: &Token<'src>, value: &Expr<'src>) -> R {
+        let _ = name;
+        self.visit_expr(object);
+        self.visit_expr(value);
+        R::default()
+    }
+
+    fn visit_this(&mut self, keyword: &Token<'src>) -> R {
+        let _ = keyword;
+        R::default()
+    }
+
+    fn visit_super(&mut self, keyword: &Token<'src>, method: &Token<'src>) -> R {
+        let _ = (keyword, method);
+        R::default()
+    }
+}
+
+/// Calls the `visitor` method for the kind of `expr`.
+pub fn walk_expr<'src, R, V>(visitor: &mut V, expr: &Expr<'src>) -> R
+where
+    R: Default,
+    V: ExprVisitor<'src, R> + ?Sized,
+{
+    match expr {
+        Expr::Binary {
+            left,
+            operator,
+            right,
+        } => visitor.visit_binary(left, operator, right),
+        Expr::Unary { operator, right } => visitor.visit_unary(operator, right),
+        Expr::Grouping { expression, span } => visitor.visit_grouping(expression, *span),
+        Expr::Literal { value, span } => visitor.visit_literal(value, *span),
+        Expr::Variable { name } => visitor.visit_variable(name),
+        Expr::Assign { name, value } => visitor.visit_assign(name, value),
+        Expr::Logical {
+            left,
+            operator,
+            right,
+        } => visitor.visit_logical(left, operator, right),
+        Expr::Call {
+            callee,
+            paren,
+            arguments,
+        } => visitor.visit_call(callee, paren, arguments),
+        Expr::Get { object, name } => visitor.visit_get(object, name),
+        Expr::Set {
+            object,
+            name,
+            value,
+        } => visitor.visit_set(object, name, value),
+        Expr::This { keyword } => visitor.visit_this(keyword),
+        Expr::Super { keyword, method } => visitor.visit_super(keyword, method),
+    }
+}
+
+/// `R` is what visiting a statement returns and `E` what visiting an
+/// expression returns, the same by default.
+pub trait StmtVisitor<'src, R: Default = (), E: Default = R>: ExprVisitor<'src, E> {
+    fn visit_stmt(&mut self, stmt: &Stmt<'src>) -> R {
+        walk_stmt(self, stmt)
+    }
+
+    fn visit_expression_stmt(&mut self, expression: &Expr<'src>, span: Span) -> R {
+        let _ = span;
+        self.visit_expr(expression);
+        R::default()
+    }
+
+    fn visit_print(&mut self, expression: &Expr<'src>, span: Span) -> R {
+        let _ = span;
+        self.visit_expr(expression);
+        R::default()
+    }
+
+    fn visit_var(&mut self, name: &Token<'src>, initializer: Option<&Expr<'src>>, span: Span) -> R {
+        let _ = (name, span);
+        if let Some(initializer) = initializer {
+            self.visit_expr(initializer);
+        }
+        R::default()
+    }
+
+    fn visit_block(&mut self, statements: &[Stmt<'src>], span: Span) -> R {
+        let _ = span;
+        for stmt in statements {
+            self.visit_stmt(stmt);
+        }
+        R::default()
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Expr<'src>,
+        then_branch: &Stmt<'src>,
+        else_branch: Option<&Stmt<'src>>,
+        span: Span,
+    ) -> R {
+        let _ = span;
+        self.visit_expr(condition);
+        self.visit_stmt(then_branch);
+        if let Some(else_branch) = else_branch {
+            self.visit_stmt(else_branch);
+        }
+        R::default()
+    }
+
+    fn visit_while(&mut self, condition: &Expr<'src>, body: &Stmt<'src>, span: Span) -> R {
+        let _ = span;
+        self.visit_expr(condition);
+        self.visit_stmt(body);
+        R::default()
+    }
+
+    /// Function declarations and the methods of classes.
+    fn visit_function(&mut self, function: &Function<'src>) -> R {
+        for stmt in &function.body {
+            self.visit_stmt(stmt);
+        }
+        R::default()
+    }
+
+    fn visit_return(&mut self, keyword: &Token<'src>, value: Option<&Expr<'src>>, span: Span) -> R {
+        let _ = (keyword, span);
+        if let Some(value) = value {
+            self.visit_expr(value);
+        }
+        R::default()
+    }
+
+    fn visit_class(
+        &mut self,
+        name: &Token<'src>,
+        superclass: Option<&Expr<'src>>,
+        methods: &[Function<'src>],
+        span: Span,
+    ) -> R {
+        let _ = (name, span);
+        if let Some(superclass) = superclass {
+            self.visit_expr(superclass);
+        }
+        for method in methods {
+            self.visit_function(method);
+        }
+        R::default()
+    }
+}
+
+/// Calls the `visitor` method for the kind of `stmt`.
+pub fn walk_stmt<'src, R, E, V>(visitor: &mut V, stmt: &Stmt<'src>) -> R
+where
+    R: Default,
+    E: Default,
+    V: StmtVisitor<'src, R, E> + ?Sized,
+{
+    match stmt {
+        Stmt::Expression { expression, span } => visitor.visit_expression_stmt(expression, *span),
+        Stmt::Print { expression, span } => visitor.visit_print(expression, *span),
+        Stmt::Var {
+            name,
+            initializer,
+            span,
+        } => visitor.visit_var(name, initializer.as_ref(), *span),
+        Stmt::Block { statements, span } => visitor.visit_block(statements, *span),
+        Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+            span,
+        } => visitor.visit_if(condition, then_branch, else_branch.as_deref(), *span),
+        Stmt::While {
+            condition,
+            body,
+            span,
+        } => visitor.visit_while(condition, body, *span),
+        Stmt::Function(function) => visitor.visit_function(function),
+        Stmt::Return {
+            keyword,
+            value,
+            span,
+        } => visitor.visit_return(keyword, value.as_ref(), *span),
+        Stmt::Class {
+            name,
+            superclass,
+            methods,
+            span,
+        } => visitor.visit_class(name, superclass.as_ref(), methods, *span),
+    }
+}
+
+/// `ExprVisitor` over a mutable tree. `visit_expr` receives the whole node,
+/// so a pass can also replace it, ie fold `1 + 2` into `3`.
+pub trait ExprVisitorMut<'src, R: Default = ()> {
+    fn visit_expr(&mut self, expr: &mut Expr<'src>) -> R {
+        walk_expr_mut(self, expr)
+    }
+
+    fn visit_binary(
+        &mut self,
+        left: &mut Expr<'src>,
+        operator: &mut Token<'src>,
+        right: &mut Expr<'src>,
+    ) -> R {
+        let _ = operator;
+        self.visit_expr(left);
+        self.visit_expr(right);
+        R::default()
+    }
+
+    fn visit_unary(&mut self, operator: &mut Token<'src>, right: &mut Expr<'src>) -> R {
+        let _ = operator;
+        self.visit_expr(right);
+        R::default()
+    }
+
+    fn visit_grouping(&mut self, expression: &mut Expr<'src>, span: &mut Span) -> R {
+        let _ = span;
+        self.visit_expr(expression);
+        R::default()
+    }
+
+    fn visit_literal(&mut self, value: &mut LiteralValue<'src>, span: &mut Span) -> R {
+        let _ = (value, span);
+        R::default()
+    }
+
+    fn visit_variable(&mut self, name: &mut Token<'src>) -> R {
+        let _ = name;
+        R::default()
+    }
+
+    fn visit_assign(&mut self, name: &mut Token<'src>, value: &mut Expr<'src>) -> R {
+        let _ = name;
+        self.visit_expr(value);
+        R::default()
+    }
+
+    fn visit_logical(
+        &mut self,
+        left: &mut Expr<'src>,
+        operator: &mut Token<'src>,
+        right: &mut Expr<'src>,
+    ) -> R {
+        let _ = operator;
+        self.visit_expr(left);
+        self.visit_expr(right);
+        R::default()
+    }
+
+    fn visit_call(
+        &mut self,
+        callee: &mut Expr<'src>,
+        paren: &mut Token<'src>,
+        arguments: &mut Vec<Expr<'src>>,
+    ) -> R {
+        let _ = paren;
+        self.visit_expr(callee);
+        for argument in arguments {
+            self.visit_expr(argument);
+        }
+        R::default()
+    }
+
+    fn visit_get(&mut self, object: &mut Expr<'src>, name: &mut Token<'src>) -> R {
+        let _ = name;
+        self.visit_expr(object);
+        R::default()
+    }
+
+    fn visit_set(
+        &mut self,
+        object: &mut Expr<'src>,
+        name: &mut Token<'src>,
+        value: &mut Expr<'src>,
+    ) -> R {
+        let _ = name;
+        self.visit_expr(object);
+        self.visit_expr(value);
+        R::default()
+    }
+
+    fn visit_this(&mut self, keyword: &mut Token<'src>) -> R {
+        let _ = keyword;
+        R::default()
+    }
+
+    fn visit_super(&mut self, keyword: &mut Token<'src>, method: &mut Token<'src>) -> R {
+        let _ = (keyword, method);
+        R::default()
+    }
+}
+
+/// Calls the `visitor` method for the kind of `expr`.
+pub fn walk_expr_mut<'src, R, V>(visitor: &mut V, expr: &mut Expr<'src>) -> R
+where
+    R: Default,
+    V: ExprVisitorMut<'src, R> + ?Sized,
+{
+    match expr {
+        Expr::Binary {
+            left,
+            operator,
+            right,
+        } => visitor.visit_binary(left, operator, right),
+        Expr::Unary { operator, right } => visitor.visit_unary(operator, right),
+        Expr::Grouping { expression, span } => visitor.visit_grouping(expression, span),
+        Expr::Literal { value, span } => visitor.visit_literal(value, span),
+        Expr::Variable { name } => visitor.visit_variable(name),
+        Expr::Assign { name, value } => visitor.visit_assign(name, value),
+        Expr::Logical {
+            left,
+            operator,
+            right,
+        } => visitor.visit_logical(left, operator, right),
+        Expr::Call {
+            callee,
+            paren,
+            arguments,
+        } => visitor.visit_call(callee, paren, arguments),
+        Expr::Get { object, name } => visitor.visit_get(object, name),
+        Expr::Set {
+            object,
+            name,
+            value,
+        } => visitor.visit_set(object, name, value),
+        Expr::This { keyword } => visitor.visit_this(keyword),
+        Expr::Super { keyword, method } => visitor.visit_super(keyword, method),
+    }
+}
+
+/// `StmtVisitor` over a mutable tree, `visit_stmt` can replace the whole
+/// statement.
+pub trait StmtVisitorMut<'src, R: Default = (), E: Default = R>: ExprVisitorMut<'src, E> {
+    fn visit_stmt(&mut self, stmt: &mut Stmt<'src>) -> R {
+        walk_stmt_mut(self, stmt)
+    }
+
+    fn visit_expression_stmt(&mut self, expression: &mut Expr<'src>, span: &mut Span) -> R {
+        let _ = span;
+        self.visit_expr(expression);
+        R::default()
+    }
+
+    fn visit_print(&mut self, expression: &mut Expr<'src>, span: &mut Span) -> R {
+        let _ = span;
+        self.visit_expr(expression);
+        R::default()
+    }
+
+    fn visit_var(
+        &mut self,
+        name: &mut Token<'src>,
+        initializer: Option<&mut Expr<'src>>,
+        span: &mut Span,
+    ) -> R {
+        let _ = (name, span);
+        if let Some(initializer) = initializer {
+            self.visit_expr(initializer);
+        }
+        R::default()
+    }
+
+    fn visit_block(&mut self, statements: &mut Vec<Stmt<'src>>, span: &mut Span) -> R {
+        let _ = span;
+        for stmt in statements {
+            self.visit_stmt(stmt);
+        }
+        R::default()
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &mut Expr<'src>,
+        then_branch: &mut Stmt<'src>,
+        else_branch: Option<&mut Stmt<'src>>,
+        span: &mut Span,
+    ) -> R {
+        let _ = span;
+        self.visit_expr(condition);
+        self.visit_stmt(then_branch);
+        if let Some(else_branch) = else_branch {
+            self.visit_stmt(else_branch);
+        }
+        R::default()
+    }
+
+    fn visit_while(
+        &mut self,
+        condition: &mut Expr<'src>,
+        body: &mut Stmt<'src>,
+        span: &mut Span,
+    ) -> R {
+        let _ = span;
+        self.visit_expr(condition);
+        self.visit_stmt(body);
+        R::default()
+    }
+
+    /// Function declarations and the methods of classes.
+    fn visit_function(&mut self, function: &mut Function<'src>) -> R {
+        for stmt in &mut function.body {
+            self.visit_stmt(stmt);
+        }
+        R::default()
+    }
+
+    fn visit_return(
+        &mut self,
+        keyword: &mut Token<'src>,
+        value: Option<&mut Expr<'src>>,
+        span: &mut Span,
+    ) -> R {
+        let _ = (keyword, span);
+        if let Some(value) = value {
+            self.visit_expr(value);
+        }
+        R::default()
+    }
+
+    fn visit_class(
+        &mut self,
+        name: &mut Token<'src>,
+        superclass: Option<&mut Expr<'src>>,
+        methods: &mut Vec<Function<'src>>,
+        span: &mut Span,
+    ) -> R {
+        let _ = (name, span);
+        if let Some(superclass) = superclass {
+            self.visit_expr(superclass);
+        }
+        for method in methods {
+            self.visit_function(method);
+        }
+        R::default()
+    }
+}
+
+/// Calls the `visitor` method for the kind of `stmt`.
+pub fn walk_stmt_mut<'src, R, E, V>(visitor: &mut V, stmt: &mut Stmt<'src>) -> R
+where
+    R: Default,
+    E: Default,
+    V: StmtVisitorMut<'src, R, E> + ?Sized,
+{
+    match stmt {
+        Stmt::Expression { expression, span } => visitor.visit_expression_stmt(expression, span),
+        Stmt::Print { expression, span } => visitor.visit_print(expression, span),
+        Stmt::Var {
+            name,
+            initializer,
+            span,
+        } => visitor.visit_var(name, initializer.as_mut(), span),
+        Stmt::Block { statements, span } => visitor.visit_block(statements, span),
+        Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+            span,
+        } => visitor.visit_if(condition, then_branch, else_branch.as_deref_mut(), span),
+        Stmt::While {
+            condition,
+            body,
+            span,
+        } => visitor.visit_while(condition, body, span),
+        Stmt::Function(function) => visitor.visit_function(function),
+        Stmt::Return {
+            keyword,
+            value,
+            span,
+        } => visitor.visit_return(keyword, value.as_mut(), span),
+        Stmt::Class {
+            name,
+            superclass,
+            methods,
+            span,
+        } => visitor.visit_class(name, superclass.as_mut(), methods, span),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::printer::sexpr_stmt;
+    use crate::test_parse::program;
+    use crate::tokens::TokenType;
+
+    /// Collects every variable read, only overriding `visit_variable`.
+    #[derive(Default)]
+    struct VariableReads<'src>(Vec<&'src str>);
+
+    impl<'src> ExprVisitor<'src> for VariableReads<'src> {
+        fn visit_variable(&mut self, name: &Token<'src>) {
+            self.0.push(name.lexeme);
+        }
+    }
+
+    impl<'src> StmtVisitor<'src> for VariableReads<'src> {}
+
+    #[test]
+    fn default_methods_reach_every_node() {
+        let statements = program(
+            "class A < B {\n  m(x) { if (x) return c(d, e.f); else print -g; }\n}\n\
+             for (var i = h; i < j; i = i + 1) { k = super.n or this.o; this.p = (l); }\n\
+             while (p and !q) {}",
+        );
+        let mut reads = VariableReads::default();
+        for stmt in &statements {
+            reads.visit_stmt(stmt);
+        }
+        assert_eq!(
+            reads.0,
+            // the desugared for loop runs its increment after the body
+            vec!["B", "x", "c", "d", "e", "g", "h", "i", "j", "l", "i", "p", "q"]
+        );
+    }
+
+    /// Counts nodes, returning the count from every method.
+    struct Depth;
+
+    impl<'src> ExprVisitor<'src, usize> for Depth {
+        fn visit_expr(&mut self, expr: &Expr<'src>) -> usize {
+            // walk the children through the defaults, then count this node
+            let below = match expr {
+                Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                    self.visit_expr(left).max(self.visit_expr(right))
+                }
+                Expr::Unary { right, .. } => self.visit_expr(right),
+                Expr::Grouping { expression, .. } => self.visit_expr(expression),
+                _ => walk_expr(self, expr),
+            };
+            below + 1
+        }
+    }
+
+    impl<'src> StmtVisitor<'src, (), usize> for Depth {
+        fn visit_print(&mut self, expression: &Expr<'src>, _span: Span) {
+            assert_eq!(self.visit_expr(expression), 4);
+        }
+    }
+
+    #[test]
+    fn statement_and_expression_results_can_differ() {
+        let statements = program("print 1 + -(2);");
+        let mut depth = Depth;
+        depth.visit_stmt(&statements[0]);
+    }
+
+    /// Folds additions of number literals.
+    struct FoldAdditions;
+
+    impl<'src> ExprVisitorMut<'src> for FoldAdditions {
+        fn visit_expr(&mut self, expr: &mut Expr<'src>) {
+            walk_expr_mut(self, expr);
+            if let Expr::Binary {
+                left,
+                operator,
+                right,
+            } = expr
+            {
+                if let (
+                    TokenType::PLUS,
+                    Expr::Literal {
+                        value: LiteralValue::Number(a),
+                        ..
+                    },
+                    Expr::Literal {
+                        value: LiteralValue::Number(b),
+                        ..
+                    },
+                ) = (operator.token_type, left.as_ref(), right.as_ref())
+                {
+                    *expr = Expr::Literal {
+                        value: LiteralValue::Number(a + b),
+                        span: expr.span(),
+                    };
+                }
+            }
+        }
+    }
+
+    impl<'src> StmtVisitorMut<'src> for FoldAdditions {}
+
+    #[test]
+    fn mutable_visitors_rewrite_the_tree() {
+        let source = "var a = 1 + 2 + 3;\nfun f() { return a + (4 + 5); }";
+        let mut statements = program(source);
+        for stmt in &mut statements {
+            FoldAdditions.visit_stmt(stmt);
+        }
+        let printed: Vec<String> = statements.iter().map(sexpr_stmt).collect();
+        assert_eq!(
+            printed,
+            vec!["(var a 6)", "(fun f () (return (+ a (group 9))))"]
+        );
+        let Stmt::Var {
+            initializer: Some(folded),
+            ..
+        } = &statements[0]
+        else {
+            panic!("expected a var declaration");
+        };
+        assert_eq!(folded.span().text(source), "1 + 2 + 3");
+    }
+}
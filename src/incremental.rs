@@ -0,0 +1,234 @@
+use std::borrow::Cow;
+use std::ops::Range;
+
+use crate::tokens::{Literal, Scanner, Span, Token};
+
+/// Replacement of the bytes in `range` of a source with `replacement`, as an
+/// editor sends it when the user types, deletes or pastes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub replacement: String,
+}
+
+impl TextEdit {
+    pub fn new(range: Range<usize>, replacement: impl Into<String>) -> Self {
+        TextEdit {
+            range,
+            replacement: replacement.into(),
+        }
+    }
+
+    /// The source after the edit.
+    pub fn apply(&self, source: &str) -> String {
+        let mut edited =
+            String::with_capacity(source.len() - self.range.len() + self.replacement.len());
+        edited.push_str(&source[..self.range.start]);
+        edited.push_str(&self.replacement);
+        edited.push_str(&source[self.range.end..]);
+        edited
+    }
+}
+
+/// Updates the tokens of `old_source` for `new_source`, which is
+/// `edit.apply(old_source)`, scanning only around the edit. The result is
+/// the same as `scan_tokens(new_source).0`.
+///
+/// Scanning restarts one token before the first token the edit touches,
+/// since a token can look up to two characters past its end (`1.` followed
+/// by a digit). The scanner carries no state from one token to the next, so
+/// once it produces a token after the edit at a position where an old token
+/// started, the rest of the old tokens are reused with their spans shifted.
+///
+/// Lex errors are not tracked across edits, a full `scan_tokens` is needed
+/// to report them.
+pub fn relex<'new>(
+    old_tokens: &[Token<'_>],
+    old_source: &str,
+    edit: &TextEdit,
+    new_source: &'new str,
+) -> Vec<Token<'new>> {
+    debug_assert_eq!(
+        new_source.len(),
+        old_source.len() - edit.range.len() + edit.replacement.len()
+    );
+    let edit_end = edit.range.start + edit.replacement.len();
+    let shift = |offset: usize| offset + edit_end - edit.range.end;
+
+    let damaged = old_tokens.partition_point(|token| token.span.end < edit.range.start);
+    let restart = damaged.saturating_sub(1);
+
+    let mut tokens: Vec<Token<'new>> = old_tokens[..restart]
+        .iter()
+        .map(|token| rebase(token, token.span, new_source))
+        .collect();
+
+    let scanner = match damaged.checked_sub(1) {
+        Some(restart) => {
+            let span = old_tokens[restart].span;
+            Scanner::resume(new_source, span.start, span.line, span.column)
+        }
+        // the edit may be in trivia before the first token
+        None => Scanner::new(new_source),
+    };
+
+    for result in scanner {
+        let Ok(token) = result else { continue };
+        if token.span.start >= edit_end {
+            let old_start = token.span.start + edit.range.end - edit_end;
+            let old_index = old_tokens.partition_point(|old| old.span.start < old_start);
+            if let Some(old) = old_tokens.get(old_index).filter(|old| {
+                old.span.start == old_start
+                    && old.token_type == token.token_type
+                    && old.span.end - old.span.start == token.span.end - token.span.start
+            }) {
+                // back in step with the old tokens
+                let (line_shift, column_shift) = (
+                    token.span.line as isize - old.span.line as isize,
+                    token.span.column as isize - old.span.column as isize,
+                );
+                let resync_line = old.span.line;
+                tokens.extend(old_tokens[old_index..].iter().map(|old| {
+                    let column = if old.span.line == resync_line {
+                        old.span.column.saturating_add_signed(column_shift)
+                    } else {
+                        old.span.column
+                    };
+                    let span = Span::new(
+                        shift(old.span.start),
+                        shift(old.span.end),
+                        old.span.line.saturating_add_signed(line_shift),
+                        column,
+                    );
+                    rebase(old, span, new_source)
+                }));
+                return tokens;
+            }
+        }
+        tokens.push(token);
+    }
+
+    tokens
+}
+
+/// A copy of `token` at `span` that borrows from `source` instead.
+fn rebase<'new>(token: &Token<'_>, span: Span, source: &'new str) -> Token<'new> {
+    let lexeme = span.text(source);
+    let literal = token.literal.as_ref().map(|literal| match literal {
+        Literal::Identifier(_) => Literal::Identifier(lexeme),
+        // the string without its quotes
+        Literal::Str(Cow::Borrowed(_)) => Literal::Str(Cow::Borrowed(&lexeme[1..lexeme.len() - 1])),
+        Literal::Str(Cow::Owned(value)) => Literal::Str(Cow::Owned(value.clone())),
+        Literal::Number(value) => Literal::Number(*value),
+    });
+    Token::new(token.token_type, lexeme, literal, span)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_rng::Rng;
+    use crate::tokens::scan_tokens;
+
+    fn check(old_source: &str, edit: TextEdit) {
+        let (old_tokens, _) = scan_tokens(old_source);
+        let new_source = edit.apply(old_source);
+        let relexed = relex(&old_tokens, old_source, &edit, &new_source);
+        let (expected, _) = scan_tokens(&new_source);
+        assert_eq!(
+            relexed, expected,
+            "relexing {:?} with {:?} gave a different result than a full scan",
+            old_source, edit
+        );
+    }
+
+    #[test]
+    fn apply_edit() {
+        assert_eq!(TextEdit::new(4..5, "yy").apply("var x = 1;"), "var yy = 1;");
+        assert_eq!(TextEdit::new(0..0, "// ").apply("x"), "// x");
+        assert_eq!(TextEdit::new(1..3, "").apply("abcd"), "ad");
+    }
+
+    #[test]
+    fn edits_inside_a_token() {
+        check("var count = 10;\nprint count;", TextEdit::new(5..5, "x"));
+        check(
+            "var count = 10;\nprint count;",
+            TextEdit::new(12..14, "2.5"),
+        );
+        check("print \"hello\";", TextEdit::new(8..10, "EY"));
+    }
+
+    #[test]
+    fn edits_that_merge_or_split_tokens() {
+        check("a b", TextEdit::new(1..2, ""));
+        check("ab", TextEdit::new(1..1, " "));
+        check("1.x", TextEdit::new(2..3, "5"));
+        check("x = 1 / 2;", TextEdit::new(7..7, "/"));
+        check("a = b == c;", TextEdit::new(7..8, ""));
+    }
+
+    #[test]
+    fn edits_that_change_lines() {
+        check("a\nb c\nd", TextEdit::new(1..2, ""));
+        check("a b c\nd e", TextEdit::new(2..2, "x\ny\n"));
+        check("a b c\nd e", TextEdit::new(0..0, "\n"));
+        check("héllo wörld\nnext", TextEdit::new(0..3, "ü"));
+    }
+
+    #[test]
+    fn edits_to_strings_and_comments() {
+        check("a \"b\" c \"d\" e", TextEdit::new(2..3, ""));
+        check("a /* b */ c", TextEdit::new(7..9, ""));
+        check("a // b\nc", TextEdit::new(5..7, "\n"));
+        check("x /* y */ z", TextEdit::new(0..0, "/*"));
+    }
+
+    #[test]
+    fn edits_at_the_ends() {
+        check("", TextEdit::new(0..0, "var x;"));
+        check("var x;", TextEdit::new(0..6, ""));
+        check("var x;", TextEdit::new(6..6, " var y;"));
+        check("var x;", TextEdit::new(0..0, "v"));
+    }
+
+    #[test]
+    fn reuses_tokens_after_the_edit() {
+        let old_source = "var a = 1;\n".repeat(100);
+        let (old_tokens, _) = scan_tokens(&old_source);
+        let edit = TextEdit::new(4..5, "bb");
+        let new_source = edit.apply(&old_source);
+        let relexed = relex(&old_tokens, &old_source, &edit, &new_source);
+        assert_eq!(relexed, scan_tokens(&new_source).0);
+        // a token after the resync point still points into the new source
+        let last_var = &relexed[relexed.len() - 6];
+        assert_eq!(last_var.lexeme, "var");
+        assert_eq!(last_var.span.line, 100);
+        assert_eq!(&new_source[last_var.span.start..last_var.span.end], "var");
+    }
+
+    const PIECES: &[&str] = &[
+        "var", "x", "é", "1", ".", "5", "0x", "e", "_", " ", "\n", "\r\n", "\"", "\\", "/", "*",
+        "=", "!", "<", "(", ")", "{", ";", "@", "and", "//", "/*", "*/",
+    ];
+
+    #[test]
+    fn random_edits_match_a_full_scan() {
+        let mut rng = Rng(0x853c_49e6_748f_ea9b);
+        for _ in 0..3000 {
+            let source: String = (0..rng.below(20)).map(|_| rng.pick(PIECES)).collect();
+            let boundaries: Vec<usize> = source
+                .char_indices()
+                .map(|(i, _)| i)
+                .chain([source.len()])
+                .collect();
+            let mut a = boundaries[rng.below(boundaries.len())];
+            let mut b = boundaries[rng.below(boundaries.len())];
+            if a > b {
+                std::mem::swap(&mut a, &mut b);
+            }
+            let replacement: String = (0..rng.below(3)).map(|_| rng.pick(PIECES)).collect();
+            check(&source, TextEdit::new(a..b, replacement));
+        }
+    }
+}
@@ -0,0 +1,11 @@
+pub mod ast;
+pub mod incremental;
+pub mod logger;
+pub mod parser;
+#[cfg(test)]
+pub(crate) mod test_parse;
+#[cfg(test)]
+pub(crate) mod test_rng;
+pub mod tokens;
+pub mod trivia;
+mod xid;
@@ -1,3 +1,593 @@
-pub fn report(line: i32, where_claus: String, message: String) {
-    println!("[line: {}]: Error: {}: {}", line, where_claus, message)
+use std::fmt::{self, Write as _};
+use std::io::{self, IsTerminal, Write as _};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::tokens::Span;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const GREEN: &str = "\x1b[1;32m";
+
+/// Environment variable read by `init_level_from_env`, ie `LOX_LOG=debug`.
+pub const LOG_ENV_VAR: &str = "LOX_LOG";
+
+/// Verbosity of the internal tracing messages, ordered from quietest to
+/// noisiest. These are for debugging the tool itself, problems in the Lox
+/// source are reported with a `Diagnostic` instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    const ALL: [Level; 6] = [
+        Level::Off,
+        Level::Error,
+        Level::Warn,
+        Level::Info,
+        Level::Debug,
+        Level::Trace,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::Off => "off",
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+
+    /// The level `steps` noisier (or quieter when negative), clamped to the
+    /// ends of the range.
+    pub fn adjusted(self, steps: i32) -> Level {
+        let index = (self as i32 + steps).clamp(0, Level::Trace as i32);
+        Level::ALL[index as usize]
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Level::ALL
+            .into_iter()
+            .find(|level| level.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("unknown log level '{}'", s))
+    }
+}
+
+// silent apart from errors and warnings unless asked otherwise
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Warn as u8);
+
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn level() -> Level {
+    Level::ALL[LEVEL.load(Ordering::Relaxed) as usize]
+}
+
+pub fn enabled(level: Level) -> bool {
+    level != Level::Off && level <= self::level()
+}
+
+/// Sets the level from `LOX_LOG` when it holds a valid level name.
+pub fn init_level_from_env() {
+    if let Some(level) = std::env::var(LOG_ENV_VAR)
+        .ok()
+        .and_then(|value| value.parse().ok())
+    {
+        set_level(level);
+    }
+}
+
+/// Writes a tracing message to stderr, use the `error!` .. `trace!` macros
+/// so the message is only formatted when its level is enabled.
+pub fn log(level: Level, args: fmt::Arguments) {
+    if enabled(level) {
+        let _ = writeln!(io::stderr(), "[{}] {}", level.as_str(), args);
+    }
+}
+
+#[macro_export]
+macro_rules! log_at {
+    ($level:expr, $($arg:tt)+) => {
+        if $crate::logger::enabled($level) {
+            $crate::logger::log($level, format_args!($($arg)+))
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => { $crate::log_at!($crate::logger::Level::Error, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => { $crate::log_at!($crate::logger::Level::Warn, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => { $crate::log_at!($crate::logger::Level::Info, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => { $crate::log_at!($crate::logger::Level::Debug, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)+) => { $crate::log_at!($crate::logger::Level::Trace, $($arg)+) };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        }
+    }
+
+    fn color(&self) -> &'static str {
+        match self {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+            Severity::Note => GREEN,
+        }
+    }
+}
+
+/// How `emit` writes diagnostics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+    // rustc style text, see `render`
+    Human,
+    // one JSON object per line, see `to_json`
+    Json,
+}
+
+static ERROR_FORMAT: AtomicU8 = AtomicU8::new(ErrorFormat::Human as u8);
+
+pub fn set_error_format(format: ErrorFormat) {
+    ERROR_FORMAT.store(format as u8, Ordering::Relaxed);
+}
+
+pub fn error_format() -> ErrorFormat {
+    match ERROR_FORMAT.load(Ordering::Relaxed) {
+        f if f == ErrorFormat::Json as u8 => ErrorFormat::Json,
+        _ => ErrorFormat::Human,
+    }
+}
+
+/// A span of source to underline, the primary label is drawn with `^~~~`
+/// and secondary labels with `---`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: Option<String>,
+    pub primary: bool,
+}
+
+/// An error or warning about the source, rendered rustc style by `render`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    // short stable identifier ie "E0002"
+    pub code: Option<&'static str>,
+    pub message: String,
+    // the first label is the primary one and gives the reported location
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>, span: Span) -> Self {
+        Diagnostic {
+            severity,
+            code: None,
+            message: message.into(),
+            labels: vec![Label {
+                span,
+                message: None,
+                primary: true,
+            }],
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>, span: Span) -> Self {
+        Diagnostic::new(Severity::Error, message, span)
+    }
+
+    pub fn warning(message: impl Into<String>, span: Span) -> Self {
+        Diagnostic::new(Severity::Warning, message, span)
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    /// Sets the text printed next to the primary underline.
+    pub fn with_primary_label(mut self, message: impl Into<String>) -> Self {
+        self.labels[0].message = Some(message.into());
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: Some(message.into()),
+            primary: false,
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn span(&self) -> Span {
+        self.labels[0].span
+    }
+}
+
+/// Writes the diagnostic to stderr in the current `error_format`, human
+/// output is coloured when stderr is a terminal.
+///
+/// Diagnostics go to stderr so they never mix with program output.
+pub fn emit(diagnostic: &Diagnostic, file_name: &str, source: &str) {
+    let rendered = match error_format() {
+        ErrorFormat::Human => {
+            let color = io::stderr().is_terminal();
+            format!("{}\n", render(diagnostic, file_name, source, color))
+        }
+        ErrorFormat::Json => format!("{}\n", to_json(diagnostic, file_name, source)),
+    };
+    // nothing sensible left to do if stderr itself is gone
+    let _ = io::stderr().write_all(rendered.as_bytes());
+}
+
+/// Serialises the diagnostic as a single line JSON object.
+///
+/// Lines and columns are 1-based, the end position points just past the
+/// last character of the span.
+pub fn to_json(diagnostic: &Diagnostic, file_name: &str, source: &str) -> String {
+    let mut out = String::from("{");
+    let _ = write!(
+        out,
+        "\"severity\":\"{}\",\"code\":{},\"message\":\"{}\",\"file\":\"{}\",",
+        diagnostic.severity.as_str(),
+        diagnostic
+            .code
+            .map(|code| format!("\"{}\"", escape_json(code)))
+            .unwrap_or_else(|| "null".to_string()),
+        escape_json(&diagnostic.message),
+        escape_json(file_name)
+    );
+    write_json_position(&mut out, diagnostic.span(), source);
+
+    out.push_str(",\"labels\":[");
+    for (index, label) in diagnostic.labels.iter().enumerate() {
+        if index > 0 {
+            out.push(',');
+        }
+        let _ = write!(
+            out,
+            "{{\"primary\":{},\"message\":{},",
+            label.primary,
+            label
+                .message
+                .as_ref()
+                .map(|message| format!("\"{}\"", escape_json(message)))
+                .unwrap_or_else(|| "null".to_string())
+        );
+        write_json_position(&mut out, label.span, source);
+        out.push('}');
+    }
+
+    out.push_str("],\"notes\":[");
+    for (index, note) in diagnostic.notes.iter().enumerate() {
+        if index > 0 {
+            out.push(',');
+        }
+        let _ = write!(out, "\"{}\"", escape_json(note));
+    }
+    out.push_str("]}");
+    out
+}
+
+/// Serialises an error that has no place in the source, ie a file that
+/// could not be read, in the same shape as `to_json` with null positions.
+pub fn message_to_json(severity: Severity, message: &str, file_name: Option<&str>) -> String {
+    format!(
+        "{{\"severity\":\"{}\",\"code\":null,\"message\":\"{}\",\"file\":{},\
+         \"start_line\":null,\"start_column\":null,\"end_line\":null,\"end_column\":null,\
+         \"labels\":[],\"notes\":[]}}",
+        severity.as_str(),
+        escape_json(message),
+        file_name
+            .map(|file_name| format!("\"{}\"", escape_json(file_name)))
+            .unwrap_or_else(|| "null".to_string())
+    )
+}
+
+fn write_json_position(out: &mut String, span: Span, source: &str) {
+    let (end_line, end_column) = span.end_position(source);
+    let _ = write!(
+        out,
+        "\"start_line\":{},\"start_column\":{},\"end_line\":{},\"end_column\":{}",
+        span.line, span.column, end_line, end_column
+    );
+}
+
+/// Escapes `value` for use inside a JSON string literal.
+pub fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Renders a diagnostic as
+///
+/// ```text
+/// error[E0002]: Unterminated string
+///  --> main.lox:1:7
+///   |
+/// 1 | print "hello
+///   |       ^~~~~~ string starts here
+///   |
+///   = note: add a closing '"'
+/// ```
+pub fn render(diagnostic: &Diagnostic, file_name: &str, source: &str, color: bool) -> String {
+    let paint = |style: &'static str| if color { style } else { "" };
+    let reset = paint(RESET);
+    let gutter_color = paint(BLUE);
+
+    let mut labels: Vec<&Label> = diagnostic.labels.iter().collect();
+    labels.sort_by_key(|label| (label.span.line, label.span.column));
+    let gutter_width = labels
+        .iter()
+        .map(|label| label.span.line.to_string().len())
+        .max()
+        .unwrap_or(1);
+    let gutter = " ".repeat(gutter_width);
+
+    let mut out = String::new();
+    let code = diagnostic
+        .code
+        .map(|code| format!("[{}]", code))
+        .unwrap_or_default();
+    let _ = writeln!(
+        out,
+        "{}{}{}{}{}: {}{}",
+        paint(diagnostic.severity.color()),
+        diagnostic.severity.as_str(),
+        code,
+        reset,
+        paint(BOLD),
+        diagnostic.message,
+        reset
+    );
+
+    let span = diagnostic.span();
+    let _ = writeln!(
+        out,
+        "{}{}-->{} {}:{}:{}",
+        gutter, gutter_color, reset, file_name, span.line, span.column
+    );
+    let _ = writeln!(out, "{} {}|{}", gutter, gutter_color, reset);
+
+    let mut previous_line = None;
+    for label in labels {
+        let line_text = label.span.line_text(source);
+        if previous_line != Some(label.span.line) {
+            let _ = writeln!(
+                out,
+                "{}{:>width$} |{} {}",
+                gutter_color,
+                label.span.line,
+                reset,
+                line_text,
+                width = gutter_width
+            );
+            previous_line = Some(label.span.line);
+        }
+
+        let line_start = label.span.line_range(source).start;
+        let prefix = &source[line_start..label.span.start.max(line_start)];
+        // copy tabs so the underline lines up however wide the terminal draws them
+        let padding: String = prefix
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        // a span running over several lines is underlined to the end of its first line
+        let underlined = &source[label.span.start..label.span.end.max(label.span.start)];
+        let underlined = underlined.split(['\r', '\n']).next().unwrap_or("");
+        let width = underlined.chars().count().max(1);
+        let (underline, label_color) = if label.primary {
+            (
+                format!("^{}", "~".repeat(width - 1)),
+                paint(diagnostic.severity.color()),
+            )
+        } else {
+            ("-".repeat(width), gutter_color)
+        };
+        let message = label
+            .message
+            .as_ref()
+            .map(|message| format!(" {}", message))
+            .unwrap_or_default();
+        let _ = writeln!(
+            out,
+            "{} {}|{} {}{}{}{}{}",
+            gutter, gutter_color, reset, padding, label_color, underline, message, reset
+        );
+    }
+
+    if !diagnostic.notes.is_empty() {
+        let _ = writeln!(out, "{} {}|{}", gutter, gutter_color, reset);
+    }
+    for note in &diagnostic.notes {
+        let _ = writeln!(
+            out,
+            "{} {}={} {}note{}: {}",
+            gutter,
+            gutter_color,
+            reset,
+            paint(BOLD),
+            reset,
+            note
+        );
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_primary_label_with_underline() {
+        let source = "var a = 1;\nprint \"hello\n";
+        let diagnostic = Diagnostic::error("Unterminated string", Span::new(17, 24, 2, 7))
+            .with_code("E0002")
+            .with_primary_label("string starts here")
+            .with_note("add a closing '\"'");
+
+        assert_eq!(
+            render(&diagnostic, "main.lox", source, false),
+            "error[E0002]: Unterminated string\n \
+             --> main.lox:2:7\n  \
+             |\n\
+             2 | print \"hello\n  \
+             |       ^~~~~~ string starts here\n  \
+             |\n  \
+             = note: add a closing '\"'\n"
+        );
+    }
+
+    #[test]
+    fn renders_secondary_labels_in_line_order() {
+        let source = "var a = 1;\n\tvar a = 2;";
+        let diagnostic = Diagnostic::warning("Variable redeclared", Span::new(16, 17, 2, 6))
+            .with_label(Span::new(4, 5, 1, 5), "first declared here");
+
+        assert_eq!(
+            render(&diagnostic, "main.lox", source, false),
+            "warning: Variable redeclared\n \
+             --> main.lox:2:6\n  \
+             |\n\
+             1 | var a = 1;\n  \
+             |     - first declared here\n\
+             2 | \tvar a = 2;\n  \
+             | \t    ^\n"
+        );
+    }
+
+    #[test]
+    fn colours_only_when_asked() {
+        let diagnostic = Diagnostic::error("Unexpected character '@'", Span::new(0, 1, 1, 1));
+        assert!(render(&diagnostic, "main.lox", "@", true).contains(RED));
+        assert!(!render(&diagnostic, "main.lox", "@", false).contains('\x1b'));
+    }
+
+    #[test]
+    fn serialises_json_on_one_line() {
+        let source = "print \"a\tb";
+        let diagnostic = Diagnostic::error("Unterminated string", Span::new(6, 10, 1, 7))
+            .with_code("E0002")
+            .with_primary_label("string starts here")
+            .with_note("add a closing '\"'");
+
+        assert_eq!(
+            to_json(&diagnostic, "dir\\main.lox", source),
+            "{\"severity\":\"error\",\"code\":\"E0002\",\"message\":\"Unterminated string\",\
+             \"file\":\"dir\\\\main.lox\",\
+             \"start_line\":1,\"start_column\":7,\"end_line\":1,\"end_column\":11,\
+             \"labels\":[{\"primary\":true,\"message\":\"string starts here\",\
+             \"start_line\":1,\"start_column\":7,\"end_line\":1,\"end_column\":11}],\
+             \"notes\":[\"add a closing '\\\"'\"]}"
+        );
+    }
+
+    #[test]
+    fn json_without_a_position() {
+        assert_eq!(
+            message_to_json(Severity::Error, "could not read `a.lox`", Some("a.lox")),
+            "{\"severity\":\"error\",\"code\":null,\"message\":\"could not read `a.lox`\",\
+             \"file\":\"a.lox\",\
+             \"start_line\":null,\"start_column\":null,\"end_line\":null,\"end_column\":null,\
+             \"labels\":[],\"notes\":[]}"
+        );
+        assert!(
+            message_to_json(Severity::Error, "invalid arguments", None).contains("\"file\":null")
+        );
+    }
+
+    #[test]
+    fn escapes_control_characters() {
+        assert_eq!(escape_json("a\u{1}\n\"b\""), "a\\u0001\\n\\\"b\\\"");
+    }
+
+    #[test]
+    fn json_without_code_or_label_messages() {
+        let diagnostic = Diagnostic::warning("Unused", Span::new(0, 1, 1, 1));
+        let json = to_json(&diagnostic, "main.lox", "a");
+        assert!(json.contains("\"code\":null"));
+        assert!(json.contains("\"message\":null"));
+        assert!(!json.contains('\n'));
+    }
+
+    #[test]
+    fn parses_levels() {
+        assert_eq!("debug".parse::<Level>(), Ok(Level::Debug));
+        assert_eq!(" TRACE ".parse::<Level>(), Ok(Level::Trace));
+        assert!("loud".parse::<Level>().is_err());
+    }
+
+    #[test]
+    fn adjusts_levels_within_range() {
+        assert_eq!(Level::Warn.adjusted(1), Level::Info);
+        assert_eq!(Level::Warn.adjusted(5), Level::Trace);
+        assert_eq!(Level::Warn.adjusted(-1), Level::Error);
+        assert_eq!(Level::Error.adjusted(-3), Level::Off);
+    }
 }
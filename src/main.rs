@@ -1,62 +1,606 @@
+use std::borrow::Cow;
 use std::env;
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io::{self, Write as _};
+use std::process::ExitCode;
 
-use std::fs::File;
-use std::io;
-use std::io::Read;
+use crafting_interpreters::ast::{printer, Stmt};
+use crafting_interpreters::logger::{self, ErrorFormat, Severity};
+use crafting_interpreters::parser::{parse_expression, parse_program, ParseError};
+use crafting_interpreters::tokens::{scan_tokens, LexError, Literal, Scanner, Token};
+use crafting_interpreters::{debug, info, trace};
 
-mod logger;
+const USAGE: &str = "Usage: crafting-interpreters [-v|-q] [--error-format=human|json] [script]
+       crafting-interpreters tokens [--format=table|json] <script>
+       crafting-interpreters parse [--dump-ast[=tree|lisp]] <script>
 
-mod tokens;
-use tokens::scan_tokens;
+  tokens          print the tokens of a script, one per row
+  parse           check a script parses, --dump-ast prints its syntax tree
+  -v, --verbose   log more about what the tool is doing, repeat for more (-vv)
+  -q, --quiet     log less, repeat to turn logging off
+  LOX_LOG         base log level: off, error, warn, info, debug or trace";
 
-fn main() {
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            error.report();
+            ExitCode::from(error.exit_code())
+        }
+    }
+}
+
+fn run() -> Result<(), CliError> {
     let args: Vec<String> = env::args().collect();
 
-    println!("Running compiler: {:?}", args);
+    logger::init_level_from_env();
 
-    if args.len() == 1 {
-        println!("Entering Repl mode");
-        repl_mode();
-        return;
+    let Options {
+        script,
+        command,
+        verbosity,
+    } = parse_args(&args[1..])?;
+    logger::set_level(logger::level().adjusted(verbosity));
+
+    debug!("Running compiler: {:?}", args);
+
+    let filename = match script {
+        Some(filename) => filename,
+        None if command.is_some() => return Err(CliError::Usage),
+        None => {
+            info!("Entering Repl mode");
+            return repl_mode();
+        }
+    };
+
+    info!("Reading file {}", filename);
+
+    let contents = load_file(&filename)?;
+
+    trace!("With text:\n{}", contents);
+
+    let (tokens, errors) = scan_tokens(&contents);
+    report_lex_errors(&errors, &filename, &contents);
+
+    let mut error_count = errors.len();
+    let output = match command {
+        Some(Command::Tokens(TokenFormat::Table)) => token_table(&tokens),
+        Some(Command::Tokens(TokenFormat::Json)) => token_json(&tokens),
+        Some(Command::Parse(_)) | None => {
+            let (statements, parse_errors) = parse_lexed(&tokens, &errors);
+            report_parse_errors(&parse_errors, &filename, &contents);
+            error_count += parse_errors.len();
+            match command {
+                Some(Command::Parse(Some(AstFormat::Tree))) => printer::tree(&statements),
+                Some(Command::Parse(Some(AstFormat::Lisp))) => statements
+                    .iter()
+                    .map(|stmt| printer::sexpr_stmt(stmt) + "\n")
+                    .collect(),
+                _ => String::new(),
+            }
+        }
+    };
+    match io::stdout().write_all(output.as_bytes()) {
+        // the reader went away, e.g. `tokens file.lox | head`
+        Err(error) if error.kind() == io::ErrorKind::BrokenPipe => {}
+        Err(error) => {
+            return Err(CliError::Io {
+                path: "<stdout>".to_string(),
+                error,
+            })
+        }
+        Ok(()) => {}
     }
 
-    let filename = &args[1];
+    if error_count == 0 {
+        Ok(())
+    } else {
+        Err(CliError::Compile {
+            file_name: filename,
+            errors: error_count,
+        })
+    }
+}
 
-    println!("Reading file {}", filename);
+/// The command line, without the program name.
+#[derive(Debug)]
+struct Options {
+    script: Option<String>,
+    command: Option<Command>,
+    // how many levels to move the log level up, down when negative
+    verbosity: i32,
+}
 
-    let contents = load_file(filename.clone());
+fn parse_args(args: &[String]) -> Result<Options, CliError> {
+    let mut script: Option<String> = None;
+    let mut command: Option<Command> = None;
+    let mut verbosity: i32 = 0;
+    let mut flags = args.iter();
+    while let Some(arg) = flags.next() {
+        match arg.as_str() {
+            "-q" | "--quiet" => verbosity -= 1,
+            "--verbose" => verbosity += 1,
+            // -v, -vv, -vvv
+            flag if flag.len() > 1
+                && flag.starts_with('-')
+                && flag[1..].chars().all(|c| c == 'v') =>
+            {
+                verbosity += flag.len() as i32 - 1
+            }
+            // -qq, -qqq
+            flag if flag.len() > 1
+                && flag.starts_with('-')
+                && flag[1..].chars().all(|c| c == 'q') =>
+            {
+                verbosity -= flag.len() as i32 - 1
+            }
+            flag if flag.starts_with("--error-format") => {
+                let format = match &flag["--error-format".len()..] {
+                    "" => flags.next().map(String::as_str),
+                    value => value.strip_prefix('='),
+                };
+                // set straight away so later usage errors are reported in it
+                match format {
+                    Some("human") => logger::set_error_format(ErrorFormat::Human),
+                    Some("json") => logger::set_error_format(ErrorFormat::Json),
+                    _ => return Err(CliError::Usage),
+                }
+            }
+            flag if flag.starts_with("--format") => {
+                let format = match &flag["--format".len()..] {
+                    "" => flags.next().map(String::as_str),
+                    value => value.strip_prefix('='),
+                };
+                command = match (command, format) {
+                    (Some(Command::Tokens(_)), Some("table")) => {
+                        Some(Command::Tokens(TokenFormat::Table))
+                    }
+                    (Some(Command::Tokens(_)), Some("json")) => {
+                        Some(Command::Tokens(TokenFormat::Json))
+                    }
+                    _ => return Err(CliError::Usage),
+                }
+            }
+            flag if flag.starts_with("--dump-ast") => {
+                command = match (command, &flag["--dump-ast".len()..]) {
+                    (Some(Command::Parse(_)), "" | "=tree") => {
+                        Some(Command::Parse(Some(AstFormat::Tree)))
+                    }
+                    (Some(Command::Parse(_)), "=lisp") => {
+                        Some(Command::Parse(Some(AstFormat::Lisp)))
+                    }
+                    _ => return Err(CliError::Usage),
+                }
+            }
+            "tokens" if command.is_none() && script.is_none() => {
+                command = Some(Command::Tokens(TokenFormat::Table))
+            }
+            "parse" if command.is_none() && script.is_none() => {
+                command = Some(Command::Parse(None))
+            }
+            // a lone `-` is still taken as a script name
+            flag if flag.starts_with('-') && flag != "-" => return Err(CliError::Usage),
+            _ if script.is_some() => return Err(CliError::Usage),
+            _ => script = Some(arg.clone()),
+        }
+    }
+    Ok(Options {
+        script,
+        command,
+        verbosity,
+    })
+}
+
+/// Why the binary gave up. Exit codes follow BSD sysexits: 64 for bad
+/// arguments, 65 for scripts that fail to compile, 74 for I/O failures, and
+/// 70 is reserved for runtime errors once scripts can run.
+#[derive(Debug)]
+enum CliError {
+    Usage,
+    Io { path: String, error: io::Error },
+    // the file is not valid UTF-8, `offset` is the first invalid byte
+    Encoding { path: String, offset: usize },
+    // the diagnostics were already reported
+    Compile { file_name: String, errors: usize },
+}
+
+impl CliError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            CliError::Usage => 64,
+            CliError::Encoding { .. } | CliError::Compile { .. } => 65,
+            CliError::Io { .. } => 74,
+        }
+    }
+
+    fn report(&self) {
+        match (self, logger::error_format()) {
+            // keep stderr to one JSON diagnostic per line
+            (CliError::Compile { .. }, ErrorFormat::Json) => {}
+            (error, ErrorFormat::Json) => eprintln!(
+                "{}",
+                logger::message_to_json(Severity::Error, &error.to_string(), error.path())
+            ),
+            (CliError::Usage, ErrorFormat::Human) => eprintln!("{}", USAGE),
+            (error, ErrorFormat::Human) => eprintln!("error: {}", error),
+        }
+    }
+
+    /// The file the error is about, if any.
+    fn path(&self) -> Option<&str> {
+        match self {
+            CliError::Usage => None,
+            CliError::Io { path, .. } | CliError::Encoding { path, .. } => Some(path),
+            CliError::Compile { file_name, .. } => Some(file_name),
+        }
+    }
+}
 
-    println!("With text:\n{}", contents);
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Usage => write!(f, "invalid arguments"),
+            CliError::Io { path, error } => write!(f, "could not read `{}`: {}", path, error),
+            CliError::Encoding { path, offset } => write!(
+                f,
+                "`{}` is not valid UTF-8, the first invalid byte is at offset {}",
+                path, offset
+            ),
+            CliError::Compile { file_name, errors } => write!(
+                f,
+                "could not compile `{}` due to {} previous error{}",
+                file_name,
+                errors,
+                if *errors == 1 { "" } else { "s" }
+            ),
+        }
+    }
+}
 
-    scan_tokens(contents);
+#[derive(Debug)]
+enum Command {
+    Tokens(TokenFormat),
+    // the format to dump the syntax tree in, if at all
+    Parse(Option<AstFormat>),
 }
 
-fn repl_mode() {
+#[derive(Debug)]
+enum AstFormat {
+    Tree,
+    Lisp,
+}
+
+#[derive(Debug)]
+enum TokenFormat {
+    Table,
+    Json,
+}
+
+/// One row per token: position, type, lexeme and literal, in aligned
+/// columns. Control characters in lexemes are escaped so every token
+/// stays on its own line.
+fn token_table(tokens: &[Token]) -> String {
+    let rows: Vec<[String; 4]> = tokens
+        .iter()
+        .map(|token| {
+            [
+                format!("{}:{}", token.span.line, token.span.column),
+                format!("{:?}", token.token_type),
+                token
+                    .lexeme
+                    .chars()
+                    .map(|c| match c {
+                        c if c.is_control() => c.escape_default().to_string(),
+                        c => c.to_string(),
+                    })
+                    .collect(),
+                match &token.literal {
+                    Some(Literal::Identifier(name)) => name.to_string(),
+                    Some(Literal::Str(value)) => format!("{:?}", value),
+                    Some(Literal::Number(value)) => value.to_string(),
+                    None => String::new(),
+                },
+            ]
+        })
+        .collect();
+
+    let mut widths = [0; 4];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut table = String::new();
+    for row in &rows {
+        let line = format!(
+            "{:<w0$}  {:<w1$}  {:<w2$}  {}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+        );
+        table.push_str(line.trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+/// A JSON array with one object per token, each on its own line.
+fn token_json(tokens: &[Token]) -> String {
+    let mut json = String::from("[\n");
+    for (index, token) in tokens.iter().enumerate() {
+        let literal = match &token.literal {
+            Some(Literal::Identifier(name)) => format!("\"{}\"", logger::escape_json(name)),
+            Some(Literal::Str(value)) => format!("\"{}\"", logger::escape_json(value)),
+            Some(Literal::Number(value)) if value.is_finite() => value.to_string(),
+            Some(Literal::Number(_)) | None => "null".to_string(),
+        };
+        let _ = write!(
+            json,
+            "{{\"line\":{},\"column\":{},\"start\":{},\"end\":{},\"type\":\"{:?}\",\"lexeme\":\"{}\",\"literal\":{}}}",
+            token.span.line,
+            token.span.column,
+            token.span.start,
+            token.span.end,
+            token.token_type,
+            logger::escape_json(token.lexeme),
+            literal
+        );
+        json.push_str(if index + 1 < tokens.len() {
+            ",\n"
+        } else {
+            "\n"
+        });
+    }
+    json.push_str("]\n");
+    json
+}
+
+/// Parses the tokens of a script. A parse error whose span touches a lex
+/// error is left out, it is the same mistake seen again.
+fn parse_lexed<'src>(
+    tokens: &[Token<'src>],
+    lex_errors: &[LexError],
+) -> (Vec<Stmt<'src>>, Vec<ParseError>) {
+    let (statements, mut parse_errors) = parse_program(tokens);
+    parse_errors.retain(|error| {
+        let span = error.span();
+        !lex_errors.iter().any(|lex_error| {
+            let lex_span = lex_error.span();
+            span.start <= lex_span.end && lex_span.start <= span.end
+        })
+    });
+    (statements, parse_errors)
+}
+
+fn report_lex_errors(errors: &[LexError], file_name: &str, source: &str) {
+    for error in errors {
+        logger::emit(&error.to_diagnostic(), file_name, source);
+    }
+}
+
+fn report_parse_errors(errors: &[ParseError], file_name: &str, source: &str) {
+    for error in errors {
+        logger::emit(&error.to_diagnostic(), file_name, source);
+    }
+}
+
+fn repl_mode() -> Result<(), CliError> {
     println!(">");
+    let mut input = String::new();
     loop {
-        let mut input = String::new();
         match io::stdin().read_line(&mut input) {
+            Ok(0) if input.is_empty() => return Ok(()),
+            // stdin closed inside a string or block comment
+            Ok(0) => {
+                let (_, source) = ast_command(&input);
+                let errors: Vec<LexError> = Scanner::new(&source).filter_map(Result::err).collect();
+                report_lex_errors(&errors, "<repl>", &input);
+                return Err(CliError::Compile {
+                    file_name: "<repl>".to_string(),
+                    errors: errors.len(),
+                });
+            }
             Ok(_n) => {
-                let tokens = scan_tokens(input);
-                println!("{:?}", tokens);
+                let (show_ast, source) = ast_command(&input);
+                let (tokens, errors): (Vec<_>, Vec<_>) =
+                    Scanner::new(&source).partition(Result::is_ok);
+                let errors: Vec<LexError> = errors.into_iter().filter_map(Result::err).collect();
+                // an open string or block comment continues on the next line
+                if errors.iter().any(LexError::is_incomplete) {
+                    println!("...");
+                    continue;
+                }
+                let tokens: Vec<_> = tokens.into_iter().filter_map(Result::ok).collect();
+                report_lex_errors(&errors, "<repl>", &input);
+                if show_ast {
+                    print_ast(&tokens, &errors, &input);
+                } else {
+                    println!("{:?}", tokens);
+                }
+                input.clear();
                 println!(">");
             }
-            Err(error) => println!("error reading repl input: {}", error),
+            Err(error) => {
+                return Err(CliError::Io {
+                    path: "<stdin>".to_string(),
+                    error,
+                })
+            }
         }
     }
 }
 
-fn load_file(file_path: String) -> String {
-    let file_result: Result<File, io::Error> = File::open(file_path);
+/// Whether the input starts with `:ast`, which prints the syntax tree
+/// instead of the tokens, and the source to scan. The command is blanked out
+/// rather than cut off so spans still point at what was typed.
+fn ast_command(input: &str) -> (bool, Cow<'_, str>) {
+    match input.strip_prefix(":ast") {
+        Some(source) => (true, Cow::Owned(format!("    {}", source))),
+        None => (false, Cow::Borrowed(input)),
+    }
+}
 
-    let mut file = match file_result {
-        Ok(s) => s,
-        Err(error) => panic!("Failed to open file: {}", error),
-    };
+/// Prints the S-expression of the input, read as a bare expression when it
+/// is one and as statements otherwise.
+fn print_ast(tokens: &[Token], lex_errors: &[LexError], source: &str) {
+    if let Ok(expr) = parse_expression(tokens) {
+        println!("{}", printer::sexpr(&expr));
+        return;
+    }
+    let (statements, errors) = parse_lexed(tokens, lex_errors);
+    report_parse_errors(&errors, "<repl>", source);
+    for stmt in &statements {
+        println!("{}", printer::sexpr_stmt(stmt));
+    }
+}
+
+fn load_file(file_path: &str) -> Result<String, CliError> {
+    let bytes = fs::read(file_path).map_err(|error| CliError::Io {
+        path: file_path.to_string(),
+        error,
+    })?;
+    String::from_utf8(bytes).map_err(|error| CliError::Encoding {
+        path: file_path.to_string(),
+        offset: error.utf8_error().valid_up_to(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes() {
+        let missing = load_file("does/not/exist.lox").unwrap_err();
+        assert_eq!(missing.exit_code(), 74);
+        assert!(missing
+            .to_string()
+            .starts_with("could not read `does/not/exist.lox`"));
+        assert_eq!(CliError::Usage.exit_code(), 64);
+        let compile = CliError::Compile {
+            file_name: "a.lox".to_string(),
+            errors: 2,
+        };
+        assert_eq!(compile.exit_code(), 65);
+        assert_eq!(
+            compile.to_string(),
+            "could not compile `a.lox` due to 2 previous errors"
+        );
+    }
+
+    #[test]
+    fn unknown_flags_are_usage_errors() {
+        for flag in ["--bogus", "-x", "--tokens"] {
+            let error = parse_args(&[flag.to_string()]).unwrap_err();
+            assert_eq!(error.exit_code(), 64, "{}", flag);
+        }
+        let options = parse_args(&["-".to_string()]).unwrap();
+        assert_eq!(options.script.as_deref(), Some("-"));
+    }
+
+    #[test]
+    fn verbosity_flags_repeat() {
+        let verbosity = |flags: &[&str]| {
+            let args: Vec<String> = flags.iter().map(|flag| flag.to_string()).collect();
+            parse_args(&args).unwrap().verbosity
+        };
+        assert_eq!(verbosity(&["-vvv"]), 3);
+        assert_eq!(verbosity(&["-qq"]), -2);
+        assert_eq!(verbosity(&["-v", "--quiet", "-qqq"]), -3);
+    }
+
+    #[test]
+    fn rejects_invalid_utf8() {
+        let path = env::temp_dir().join(format!("lox-invalid-{}.lox", std::process::id()));
+        fs::write(&path, b"var x = \"\xff\";").unwrap();
+        let error = load_file(path.to_str().unwrap()).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(error.exit_code(), 65);
+        assert!(error
+            .to_string()
+            .ends_with("the first invalid byte is at offset 9"));
+    }
+
+    #[test]
+    fn lex_errors_are_reported_once() {
+        for source in ["var x = 1x;", r#"var s = "x\q";"#] {
+            let (tokens, errors) = scan_tokens(source);
+            let (_, parse_errors) = parse_lexed(&tokens, &errors);
+            assert_eq!(errors.len() + parse_errors.len(), 1, "{}", source);
+        }
+
+        // a lex error does not hide the syntax errors around it
+        let source = "print 1;\nprint a +;\nvar b = \"x\\q\";\nvar c = @;\n";
+        let (tokens, errors) = scan_tokens(source);
+        let (statements, parse_errors) = parse_lexed(&tokens, &errors);
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| (error.code(), error.span().line))
+                .collect::<Vec<_>>(),
+            vec![("E0004", 3), ("E0001", 4)]
+        );
+        // `var c = ;` is left out, its error is the stray '@'
+        assert_eq!(
+            parse_errors
+                .iter()
+                .map(|error| (error.code(), error.span().line))
+                .collect::<Vec<_>>(),
+            vec![("E0101", 2)]
+        );
+        assert_eq!(statements.len(), 2);
+    }
+
+    #[test]
+    fn ast_command_keeps_columns() {
+        let input = ":ast print 1 +;";
+        let (show_ast, source) = ast_command(input);
+        assert!(show_ast);
+        let (tokens, errors) = scan_tokens(&source);
+        let (_, parse_errors) = parse_lexed(&tokens, &errors);
+        let span = parse_errors[0].span();
+        assert_eq!((span.line, span.column), (1, 15));
+        assert_eq!(span.text(input), ";");
+        assert_eq!(ast_command("print 1;"), (false, Cow::Borrowed("print 1;")));
+    }
+
+    #[test]
+    fn token_table_aligns_columns() {
+        // the tab inside the string is shown escaped
+        let (tokens, _) = scan_tokens("var name = \"a\tb\";\nprint 1.5;");
+        assert_eq!(
+            token_table(&tokens),
+            r#"1:1   VAR         var
+1:5   IDENTIFIER  name    name
+1:10  EQUAL       =
+1:12  STRING      "a\tb"  "a\tb"
+1:17  SemiColon   ;
+2:1   PRINT       print
+2:7   NUMBER      1.5     1.5
+2:10  SemiColon   ;
+2:11  EOF
+"#
+        );
+    }
 
-    let mut contents_buffer = String::new();
-    match file.read_to_string(&mut contents_buffer) {
-        Ok(contents) => contents.to_string(),
-        Err(error) => panic!("Failed reading contents of file: {}", error),
+    #[test]
+    fn token_json_is_an_array_of_tokens() {
+        let (tokens, _) = scan_tokens("x \"q\\\"\" 2");
+        assert_eq!(
+            token_json(&tokens),
+            r#"[
+{"line":1,"column":1,"start":0,"end":1,"type":"IDENTIFIER","lexeme":"x","literal":"x"},
+{"line":1,"column":3,"start":2,"end":7,"type":"STRING","lexeme":"\"q\\\"\"","literal":"q\""},
+{"line":1,"column":9,"start":8,"end":9,"type":"NUMBER","lexeme":"2","literal":2},
+{"line":1,"column":10,"start":9,"end":9,"type":"EOF","lexeme":"","literal":null}
+]
+"#
+        );
     }
 }
@@ -0,0 +1,1234 @@
+use std::fmt;
+
+use crate::ast::{Expr, Function, LiteralValue, Stmt};
+use crate::logger::Diagnostic;
+use crate::tokens::{Literal, Span, Token, TokenType};
+use crate::{debug, trace};
+
+/// Calls and functions take at most this many arguments, as in the reference
+/// interpreter.
+const MAX_ARGUMENTS: usize = 255;
+
+/// How deeply groupings, unary operators, blocks and the like can nest. A
+/// grouping costs the most, a dozen recursive calls that take about 27 KiB
+/// of stack in a debug build, so this many still fit in the 2 MiB stack of
+/// a spawned thread.
+const MAX_DEPTH: usize = 64;
+
+/// A problem found while parsing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    // the next token does not fit the grammar ie `1 +` or a missing ')',
+    // expected is the token type that would have fit, if there is just one,
+    // and message describes what would have fit ie "')' after arguments"
+    UnexpectedToken {
+        expected: Option<TokenType>,
+        message: &'static str,
+        found: TokenType,
+        lexeme: String,
+        span: Span,
+    },
+    // the left side of '=' is not a variable or property ie `a + b = c`
+    InvalidAssignmentTarget {
+        span: Span,
+    },
+    // a call with more than MAX_ARGUMENTS arguments, spans the first extra one
+    TooManyArguments {
+        span: Span,
+    },
+    // a function with more than MAX_ARGUMENTS parameters, spans the first extra one
+    TooManyParameters {
+        span: Span,
+    },
+    // nesting deeper than MAX_DEPTH, spans the token that went too deep
+    TooDeeplyNested {
+        span: Span,
+    },
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::UnexpectedToken { span, .. }
+            | ParseError::InvalidAssignmentTarget { span }
+            | ParseError::TooManyArguments { span }
+            | ParseError::TooManyParameters { span }
+            | ParseError::TooDeeplyNested { span } => *span,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseError::UnexpectedToken { .. } => "E0101",
+            ParseError::InvalidAssignmentTarget { .. } => "E0102",
+            ParseError::TooManyArguments { .. } => "E0103",
+            ParseError::TooManyParameters { .. } => "E0104",
+            ParseError::TooDeeplyNested { .. } => "E0105",
+        }
+    }
+
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::error(self.to_string(), self.span()).with_code(self.code());
+        match self {
+            ParseError::UnexpectedToken { message, .. } => {
+                diagnostic.with_primary_label(format!("expected {}", message))
+            }
+            ParseError::InvalidAssignmentTarget { .. } => diagnostic
+                .with_primary_label("cannot be assigned to")
+                .with_note("only variables and properties can be assigned to"),
+            ParseError::TooManyArguments { .. } => diagnostic
+                .with_primary_label(format!("argument {} of this call", MAX_ARGUMENTS + 1))
+                .with_note(format!(
+                    "a call can have at most {} arguments",
+                    MAX_ARGUMENTS
+                )),
+            ParseError::TooManyParameters { .. } => diagnostic
+                .with_primary_label(format!("parameter {} of this function", MAX_ARGUMENTS + 1))
+                .with_note(format!(
+                    "a function can have at most {} parameters",
+                    MAX_ARGUMENTS
+                )),
+            ParseError::TooDeeplyNested { .. } => diagnostic
+                .with_primary_label(format!("level {} of nesting", MAX_DEPTH + 1))
+                .with_note(format!(
+                    "code can be nested at most {} levels deep",
+                    MAX_DEPTH
+                )),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnexpectedToken {
+                message,
+                found: TokenType::EOF,
+                ..
+            } => write!(f, "Expected {}, found end of file", message),
+            ParseError::UnexpectedToken {
+                message, lexeme, ..
+            } => write!(f, "Expected {}, found '{}'", message, lexeme),
+            ParseError::InvalidAssignmentTarget { .. } => write!(f, "Invalid assignment target"),
+            ParseError::TooManyArguments { .. } => {
+                write!(f, "Too many arguments, the limit is {}", MAX_ARGUMENTS)
+            }
+            ParseError::TooManyParameters { .. } => {
+                write!(f, "Too many parameters, the limit is {}", MAX_ARGUMENTS)
+            }
+            ParseError::TooDeeplyNested { .. } => {
+                write!(f, "Nested too deeply, the limit is {}", MAX_DEPTH)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Recursive descent parser over the tokens of `scan_tokens`, one method
+/// per grammar rule, from lowest to highest precedence:
+///
+/// ```text
+/// program    → declaration* EOF
+/// declaration→ classDecl | funDecl | varDecl | statement
+/// classDecl  → "class" IDENTIFIER ( "<" IDENTIFIER )? "{" function* "}"
+/// funDecl    → "fun" function
+/// function   → IDENTIFIER "(" parameters? ")" block
+/// varDecl    → "var" IDENTIFIER ( "=" expression )? ";"
+/// statement  → exprStmt | forStmt | ifStmt | printStmt | returnStmt
+///            | whileStmt | block
+/// forStmt    → "for" "(" ( varDecl | exprStmt | ";" ) expression? ";"
+///              expression? ")" statement
+/// ifStmt     → "if" "(" expression ")" statement ( "else" statement )?
+/// printStmt  → "print" expression ";"
+/// returnStmt → "return" expression? ";"
+/// whileStmt  → "while" "(" expression ")" statement
+/// block      → "{" declaration* "}"
+/// exprStmt   → expression ";"
+///
+/// expression → assignment
+/// assignment → ( call "." )? IDENTIFIER "=" assignment | logic_or
+/// logic_or   → logic_and ( "or" logic_and )*
+/// logic_and  → equality ( "and" equality )*
+/// equality   → comparison ( ( "!=" | "==" ) comparison )*
+/// comparison → term ( ( ">" | ">=" | "<" | "<=" ) term )*
+/// term       → factor ( ( "-" | "+" ) factor )*
+/// factor     → unary ( ( "/" | "*" ) unary )*
+/// unary      → ( "!" | "-" ) unary | call
+/// call       → primary ( "(" arguments? ")" | "." IDENTIFIER )*
+/// primary    → NUMBER | STRING | "true" | "false" | "nil" | "this"
+///            | IDENTIFIER | "(" expression ")" | "super" "." IDENTIFIER
+/// ```
+///
+/// The tokens must end with EOF, as the scanner's always do.
+///
+/// Errors that leave the parser lost, like a missing ';', unwind to the
+/// enclosing declaration, which records the error and skips ahead to the
+/// next statement boundary (`synchronize`). Errors that do not, like an
+/// invalid assignment target, are recorded where they are found and parsing
+/// carries on. Either way they end up in `errors`, so one pass reports every
+/// error in the source.
+pub struct Parser<'tok, 'src> {
+    tokens: &'tok [Token<'src>],
+    current: usize,
+    errors: Vec<ParseError>,
+    // how many blocks the current token is inside
+    block_depth: usize,
+    // how many nested rules the current token is inside, see `nested`
+    depth: usize,
+}
+
+impl<'tok, 'src> Parser<'tok, 'src> {
+    pub fn new(tokens: &'tok [Token<'src>]) -> Self {
+        Parser {
+            tokens,
+            current: 0,
+            errors: Vec::new(),
+            block_depth: 0,
+            depth: 0,
+        }
+    }
+
+    /// The errors recorded so far.
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    /// Parses a declaration, on an error records it, skips to the next
+    /// statement and returns None.
+    fn declaration_or_recover(&mut self) -> Option<Stmt<'src>> {
+        let start = self.current;
+        match self.declaration() {
+            Ok(stmt) => Some(stmt),
+            Err(error) => {
+                debug!("recovering from {}", error);
+                self.errors.push(error);
+                self.synchronize(start);
+                None
+            }
+        }
+    }
+
+    /// Discards tokens until the start of the next statement: just past a
+    /// ';', or before a keyword that starts a statement or, inside a block,
+    /// the '}' that ends it. The declaration that failed started at token
+    /// `start`, if the error was right there that token is skipped so
+    /// parsing always moves on. A '}' that closes a '{' of the failed
+    /// declaration itself, like a class body, is skipped too.
+    fn synchronize(&mut self, start: usize) {
+        if self.current == start {
+            self.advance();
+        }
+        // '{' minus '}' from `start` up to the current token
+        let mut open_braces: isize = self.tokens[start..self.current]
+            .iter()
+            .map(|token| brace_balance(token.token_type))
+            .sum();
+        while !self.is_at_end() {
+            if self.previous().token_type == TokenType::SemiColon {
+                return;
+            }
+            match self.peek().token_type {
+                TokenType::CLASS
+                | TokenType::FUN
+                | TokenType::VAR
+                | TokenType::FOR
+                | TokenType::IF
+                | TokenType::While
+                | TokenType::PRINT
+                | TokenType::RETURN => return,
+                TokenType::RightBrace if self.block_depth > 0 && open_braces == 0 => return,
+                token_type => {
+                    open_braces += brace_balance(token_type);
+                    self.advance();
+                }
+            }
+        }
+    }
+
+    pub fn declaration(&mut self) -> Result<Stmt<'src>, ParseError> {
+        match self.peek().token_type {
+            TokenType::CLASS => self.class_declaration(),
+            TokenType::FUN => {
+                let start = self.advance().span;
+                let mut function = self.function("function name")?;
+                function.span = start.to(function.span);
+                Ok(Stmt::Function(function))
+            }
+            TokenType::VAR => self.var_declaration(),
+            _ => self.statement(),
+        }
+    }
+
+    fn class_declaration(&mut self) -> Result<Stmt<'src>, ParseError> {
+        let start = self.advance().span;
+        let name = self.consume(TokenType::IDENTIFIER, "class name")?.clone();
+        let superclass = if self.match_types(&[TokenType::LESS]) {
+            let name = self
+                .consume(TokenType::IDENTIFIER, "superclass name")?
+                .clone();
+            Some(Expr::Variable { name })
+        } else {
+            None
+        };
+        self.consume(TokenType::LeftBrace, "'{' before class body")?;
+
+        let mut methods = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            methods.push(self.function("method name")?);
+        }
+        let end = self.consume(TokenType::RightBrace, "'}' after class body")?;
+
+        Ok(Stmt::Class {
+            name,
+            superclass,
+            methods,
+            span: start.to(end.span),
+        })
+    }
+
+    /// The name, parameters and body of a function or method, `expected`
+    /// describes the name in error messages.
+    fn function(&mut self, expected: &'static str) -> Result<Function<'src>, ParseError> {
+        let name = self.consume(TokenType::IDENTIFIER, expected)?.clone();
+        self.consume(TokenType::LeftParen, "'(' after the name")?;
+        let mut params = Vec::new();
+        if !self.check(TokenType::RightParen) {
+            loop {
+                if params.len() == MAX_ARGUMENTS {
+                    self.errors.push(ParseError::TooManyParameters {
+                        span: self.peek().span,
+                    });
+                }
+                params.push(
+                    self.consume(TokenType::IDENTIFIER, "parameter name")?
+                        .clone(),
+                );
+                if !self.match_types(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightParen, "')' after parameters")?;
+        self.consume(TokenType::LeftBrace, "'{' before the body")?;
+        let (body, end) = self.block()?;
+
+        Ok(Function {
+            span: name.span.to(end),
+            name,
+            params,
+            body,
+        })
+    }
+
+    fn var_declaration(&mut self) -> Result<Stmt<'src>, ParseError> {
+        let start = self.advance().span;
+        let name = self
+            .consume(TokenType::IDENTIFIER, "variable name")?
+            .clone();
+        let initializer = if self.match_types(&[TokenType::EQUAL]) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        let end = self.consume(TokenType::SemiColon, "';' after variable declaration")?;
+
+        Ok(Stmt::Var {
+            name,
+            initializer,
+            span: start.to(end.span),
+        })
+    }
+
+    fn statement(&mut self) -> Result<Stmt<'src>, ParseError> {
+        match self.peek().token_type {
+            TokenType::FOR => self.for_statement(),
+            TokenType::IF => self.if_statement(),
+            TokenType::PRINT => {
+                let start = self.advance().span;
+                let expression = self.expression()?;
+                let end = self.consume(TokenType::SemiColon, "';' after value")?;
+                Ok(Stmt::Print {
+                    expression,
+                    span: start.to(end.span),
+                })
+            }
+            TokenType::RETURN => {
+                let keyword = self.advance().clone();
+                let value = if self.check(TokenType::SemiColon) {
+                    None
+                } else {
+                    Some(self.expression()?)
+                };
+                let end = self.consume(TokenType::SemiColon, "';' after return value")?;
+                Ok(Stmt::Return {
+                    span: keyword.span.to(end.span),
+                    keyword,
+                    value,
+                })
+            }
+            TokenType::While => {
+                let start = self.advance().span;
+                let condition = self.condition("'(' after 'while'")?;
+                let body = self.nested(Self::statement)?;
+                Ok(Stmt::While {
+                    span: start.to(body.span()),
+                    condition,
+                    body: Box::new(body),
+                })
+            }
+            TokenType::LeftBrace => {
+                let start = self.advance().span;
+                let (statements, end) = self.block()?;
+                Ok(Stmt::Block {
+                    statements,
+                    span: start.to(end),
+                })
+            }
+            _ => self.expression_statement(),
+        }
+    }
+
+    fn expression_statement(&mut self) -> Result<Stmt<'src>, ParseError> {
+        let expression = self.expression()?;
+        let end = self.consume(TokenType::SemiColon, "';' after expression")?;
+        Ok(Stmt::Expression {
+            span: expression.span().to(end.span),
+            expression,
+        })
+    }
+
+    /// The parenthesised condition after `if` or `while`, `expected`
+    /// describes the '(' in error messages.
+    fn condition(&mut self, expected: &'static str) -> Result<Expr<'src>, ParseError> {
+        self.consume(TokenType::LeftParen, expected)?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "')' after condition")?;
+        Ok(condition)
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt<'src>, ParseError> {
+        let start = self.advance().span;
+        let condition = self.condition("'(' after 'if'")?;
+        let then_branch = self.nested(Self::statement)?;
+        // an else belongs to the nearest if
+        let else_branch = if self.match_types(&[TokenType::ELSE]) {
+            Some(Box::new(self.nested(Self::statement)?))
+        } else {
+            None
+        };
+        let end = else_branch
+            .as_ref()
+            .map_or(then_branch.span(), |branch| branch.span());
+
+        Ok(Stmt::If {
+            condition,
+            then_branch: Box::new(then_branch),
+            else_branch,
+            span: start.to(end),
+        })
+    }
+
+    /// Parses a for loop into the equivalent while loop:
+    ///
+    /// ```text
+    /// { initializer; while (condition) { body; increment; } }
+    /// ```
+    ///
+    /// A missing condition is `true`. Every node made up here gets the span
+    /// of the part of the for loop it came from.
+    fn for_statement(&mut self) -> Result<Stmt<'src>, ParseError> {
+        let start = self.advance().span;
+        self.consume(TokenType::LeftParen, "'(' after 'for'")?;
+
+        let initializer = match self.peek().token_type {
+            TokenType::SemiColon => {
+                self.advance();
+                None
+            }
+            TokenType::VAR => Some(self.var_declaration()?),
+            _ => Some(self.expression_statement()?),
+        };
+
+        let condition = if self.check(TokenType::SemiColon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        let condition_end = self.consume(TokenType::SemiColon, "';' after loop condition")?;
+        let condition = condition.unwrap_or(Expr::Literal {
+            value: LiteralValue::Bool(true),
+            span: condition_end.span,
+        });
+
+        let increment = if self.check(TokenType::RightParen) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::RightParen, "')' after for clauses")?;
+
+        let mut body = self.nested(Self::statement)?;
+        let span = start.to(body.span());
+
+        if let Some(increment) = increment {
+            body = Stmt::Block {
+                span: body.span(),
+                statements: vec![
+                    body,
+                    Stmt::Expression {
+                        span: increment.span(),
+                        expression: increment,
+                    },
+                ],
+            };
+        }
+        let mut desugared = Stmt::While {
+            condition,
+            body: Box::new(body),
+            span,
+        };
+        if let Some(initializer) = initializer {
+            desugared = Stmt::Block {
+                statements: vec![initializer, desugared],
+                span,
+            };
+        }
+        Ok(desugared)
+    }
+
+    /// The statements of a block whose '{' was just consumed, with the span
+    /// of its closing '}'.
+    fn block(&mut self) -> Result<(Vec<Stmt<'src>>, Span), ParseError> {
+        self.nested(|parser| {
+            let mut statements = Vec::new();
+            parser.block_depth += 1;
+            while !parser.check(TokenType::RightBrace) && !parser.is_at_end() {
+                statements.extend(parser.declaration_or_recover());
+            }
+            parser.block_depth -= 1;
+            let end = parser.consume(TokenType::RightBrace, "'}' after block")?;
+            Ok((statements, end.span))
+        })
+    }
+
+    pub fn expression(&mut self) -> Result<Expr<'src>, ParseError> {
+        self.assignment()
+    }
+
+    fn assignment(&mut self) -> Result<Expr<'src>, ParseError> {
+        let target = self.or()?;
+
+        if self.match_types(&[TokenType::EQUAL]) {
+            // parse the value first, `a = b = c` assigns right to left
+            let value = Box::new(self.nested(Self::assignment)?);
+            return match target {
+                Expr::Variable { name } => Ok(Expr::Assign { name, value }),
+                Expr::Get { object, name } => Ok(Expr::Set {
+                    object,
+                    name,
+                    value,
+                }),
+                // reported without unwinding, the parser is not lost
+                target => {
+                    self.errors.push(ParseError::InvalidAssignmentTarget {
+                        span: target.span(),
+                    });
+                    Ok(target)
+                }
+            };
+        }
+
+        Ok(target)
+    }
+
+    fn or(&mut self) -> Result<Expr<'src>, ParseError> {
+        let mut expr = self.and()?;
+        while self.match_types(&[TokenType::OR]) {
+            let operator = self.previous().clone();
+            let right = self.and()?;
+            expr = Expr::Logical {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            };
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr<'src>, ParseError> {
+        let mut expr = self.equality()?;
+        while self.match_types(&[TokenType::AND]) {
+            let operator = self.previous().clone();
+            let right = self.equality()?;
+            expr = Expr::Logical {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            };
+        }
+        Ok(expr)
+    }
+
+    fn equality(&mut self) -> Result<Expr<'src>, ParseError> {
+        self.binary(
+            &[TokenType::BangEqual, TokenType::EqualEqual],
+            Self::comparison,
+        )
+    }
+
+    fn comparison(&mut self) -> Result<Expr<'src>, ParseError> {
+        self.binary(
+            &[
+                TokenType::GREATER,
+                TokenType::GreatEqual,
+                TokenType::LESS,
+                TokenType::LessEqual,
+            ],
+            Self::term,
+        )
+    }
+
+    fn term(&mut self) -> Result<Expr<'src>, ParseError> {
+        self.binary(&[TokenType::MINUS, TokenType::PLUS], Self::factor)
+    }
+
+    fn factor(&mut self) -> Result<Expr<'src>, ParseError> {
+        self.binary(&[TokenType::SLASH, TokenType::STAR], Self::unary)
+    }
+
+    /// A left associative chain of `operand`s joined by any of `operators`.
+    fn binary(
+        &mut self,
+        operators: &[TokenType],
+        operand: fn(&mut Self) -> Result<Expr<'src>, ParseError>,
+    ) -> Result<Expr<'src>, ParseError> {
+        let mut expr = operand(self)?;
+        while self.match_types(operators) {
+            let operator = self.previous().clone();
+            let right = operand(self)?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            };
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr<'src>, ParseError> {
+        if self.match_types(&[TokenType::BANG, TokenType::MINUS]) {
+            let operator = self.previous().clone();
+            let right = self.nested(Self::unary)?;
+            return Ok(Expr::Unary {
+                operator,
+                right: Box::new(right),
+            });
+        }
+        self.call()
+    }
+
+    fn call(&mut self) -> Result<Expr<'src>, ParseError> {
+        let mut expr = self.primary()?;
+        loop {
+            if self.match_types(&[TokenType::LeftParen]) {
+                expr = self.finish_call(expr)?;
+            } else if self.match_types(&[TokenType::DOT]) {
+                let name = self
+                    .consume(TokenType::IDENTIFIER, "property name after '.'")?
+                    .clone();
+                expr = Expr::Get {
+                    object: Box::new(expr),
+                    name,
+                };
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    /// Parses the arguments of a call whose '(' was just consumed.
+    fn finish_call(&mut self, callee: Expr<'src>) -> Result<Expr<'src>, ParseError> {
+        let mut arguments = Vec::new();
+        if !self.check(TokenType::RightParen) {
+            loop {
+                if arguments.len() == MAX_ARGUMENTS {
+                    self.errors.push(ParseError::TooManyArguments {
+                        span: self.peek().span,
+                    });
+                }
+                arguments.push(self.nested(Self::expression)?);
+                if !self.match_types(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        let paren = self
+            .consume(TokenType::RightParen, "')' after arguments")?
+            .clone();
+        Ok(Expr::Call {
+            callee: Box::new(callee),
+            paren,
+            arguments,
+        })
+    }
+
+    fn primary(&mut self) -> Result<Expr<'src>, ParseError> {
+        let token = self.peek().clone();
+        trace!("primary {:?}", token.token_type);
+        let value = match (token.token_type, &token.literal) {
+            (TokenType::FALSE, _) => LiteralValue::Bool(false),
+            (TokenType::TRUE, _) => LiteralValue::Bool(true),
+            (TokenType::NIL, _) => LiteralValue::Nil,
+            (TokenType::NUMBER, Some(Literal::Number(value))) => LiteralValue::Number(*value),
+            (TokenType::STRING, Some(Literal::Str(value))) => LiteralValue::Str(value.clone()),
+            (TokenType::THIS, _) => {
+                self.advance();
+                return Ok(Expr::This { keyword: token });
+            }
+            (TokenType::IDENTIFIER, _) => {
+                self.advance();
+                return Ok(Expr::Variable { name: token });
+            }
+            (TokenType::SUPER, _) => {
+                self.advance();
+                self.consume(TokenType::DOT, "'.' after 'super'")?;
+                let method = self
+                    .consume(TokenType::IDENTIFIER, "superclass method name")?
+                    .clone();
+                return Ok(Expr::Super {
+                    keyword: token,
+                    method,
+                });
+            }
+            (TokenType::LeftParen, _) => {
+                self.advance();
+                let expression = self.nested(Self::expression)?;
+                let close = self.consume(TokenType::RightParen, "')' after expression")?;
+                return Ok(Expr::Grouping {
+                    expression: Box::new(expression),
+                    span: token.span.to(close.span),
+                });
+            }
+            _ => return Err(self.unexpected(None, "expression")),
+        };
+        self.advance();
+        Ok(Expr::Literal {
+            value,
+            span: token.span,
+        })
+    }
+
+    /// Runs `rule` one level deeper, every rule that can recurse without
+    /// bound goes through here. Past MAX_DEPTH levels it fails instead, so
+    /// deeply nested input does not overflow a 2 MiB stack.
+    fn nested<T>(
+        &mut self,
+        rule: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if self.depth == MAX_DEPTH {
+            return Err(ParseError::TooDeeplyNested {
+                span: self.peek().span,
+            });
+        }
+        self.depth += 1;
+        let result = rule(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Consumes the next token if it has one of `types`.
+    fn match_types(&mut self, types: &[TokenType]) -> bool {
+        if types.iter().any(|token_type| self.check(*token_type)) {
+            self.advance();
+            return true;
+        }
+        false
+    }
+
+    /// Consumes the next token, which must be a `token_type`, `message`
+    /// describes it for the error otherwise.
+    fn consume(
+        &mut self,
+        token_type: TokenType,
+        message: &'static str,
+    ) -> Result<&'tok Token<'src>, ParseError> {
+        if self.check(token_type) {
+            return Ok(self.advance());
+        }
+        Err(self.unexpected(Some(token_type), message))
+    }
+
+    fn unexpected(&self, expected: Option<TokenType>, message: &'static str) -> ParseError {
+        let found = self.peek();
+        ParseError::UnexpectedToken {
+            expected,
+            message,
+            found: found.token_type,
+            lexeme: found.lexeme.to_string(),
+            span: found.span,
+        }
+    }
+
+    fn check(&self, token_type: TokenType) -> bool {
+        self.peek().token_type == token_type
+    }
+
+    fn advance(&mut self) -> &'tok Token<'src> {
+        if !self.is_at_end() {
+            self.current += 1;
+        }
+        self.previous()
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.peek().token_type == TokenType::EOF
+    }
+
+    /// The next token, EOF once the tokens run out.
+    fn peek(&self) -> &'tok Token<'src> {
+        &self.tokens[self.current.min(self.tokens.len() - 1)]
+    }
+
+    fn previous(&self) -> &'tok Token<'src> {
+        &self.tokens[self.current.saturating_sub(1)]
+    }
+}
+
+/// How a token changes the count of open braces.
+fn brace_balance(token_type: TokenType) -> isize {
+    match token_type {
+        TokenType::LeftBrace => 1,
+        TokenType::RightBrace => -1,
+        _ => 0,
+    }
+}
+
+/// Parses the tokens of a whole program into its declarations, together
+/// with every error found along the way. A declaration the parser had to
+/// synchronise past is left out, one with only an invalid assignment target
+/// or too many arguments or parameters is kept.
+pub fn parse_program<'src>(tokens: &[Token<'src>]) -> (Vec<Stmt<'src>>, Vec<ParseError>) {
+    let mut parser = Parser::new(tokens);
+    let mut statements = Vec::new();
+    while !parser.is_at_end() {
+        statements.extend(parser.declaration_or_recover());
+    }
+    (statements, parser.errors)
+}
+
+/// Parses `tokens` as a single expression followed by EOF, stopping at the
+/// first error.
+pub fn parse_expression<'src>(tokens: &[Token<'src>]) -> Result<Expr<'src>, ParseError> {
+    let mut parser = Parser::new(tokens);
+    let expr = parser.expression()?;
+    parser.consume(TokenType::EOF, "end of expression")?;
+    match parser.errors.into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok(expr),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::printer::{sexpr, sexpr_stmt};
+    use crate::test_parse::{parse, parse_with_errors};
+    use crate::tokens::scan_tokens;
+
+    fn parse_statements(source: &str) -> Result<Vec<Stmt<'_>>, ParseError> {
+        let (statements, mut errors) = parse_with_errors(source);
+        match errors.is_empty() {
+            true => Ok(statements),
+            false => Err(errors.remove(0)),
+        }
+    }
+
+    /// The message and line of every error in `source`.
+    fn errors_of(source: &str) -> Vec<(String, usize)> {
+        parse_with_errors(source)
+            .1
+            .iter()
+            .map(|error| (error.to_string(), error.span().line))
+            .collect()
+    }
+
+    fn assert_program(source: &str, expected: &[&str]) {
+        match parse_statements(source) {
+            Ok(statements) => {
+                let printed: Vec<String> = statements.iter().map(sexpr_stmt).collect();
+                assert_eq!(printed, expected, "parsing {:?}", source);
+            }
+            Err(error) => panic!("parsing {:?} failed: {}", source, error),
+        }
+    }
+
+    fn assert_parses_to(source: &str, expected: &str) {
+        match parse(source) {
+            Ok(expr) => assert_eq!(sexpr(&expr), expected, "parsing {:?}", source),
+            Err(error) => panic!("parsing {:?} failed: {}", source, error),
+        }
+    }
+
+    #[test]
+    fn literals() {
+        assert_parses_to("123", "123");
+        assert_parses_to("1.5", "1.5");
+        assert_parses_to("\"a\\tb\"", "\"a\\tb\"");
+        assert_parses_to("true", "true");
+        assert_parses_to("false", "false");
+        assert_parses_to("nil", "nil");
+    }
+
+    #[test]
+    fn precedence() {
+        assert_parses_to("1 + 2 * 3", "(+ 1 (* 2 3))");
+        assert_parses_to("(1 + 2) * 3", "(* (group (+ 1 2)) 3)");
+        assert_parses_to("-123 * (45.67)", "(* (- 123) (group 45.67))");
+        assert_parses_to("1 < 2 == 3 >= 4", "(== (< 1 2) (>= 3 4))");
+        assert_parses_to("!!a != b", "(!= (! (! a)) b)");
+        assert_parses_to("a or b and c == d", "(or a (and b (== c d)))");
+    }
+
+    #[test]
+    fn binary_operators_are_left_associative() {
+        assert_parses_to("1 - 2 - 3", "(- (- 1 2) 3)");
+        assert_parses_to("8 / 4 / 2", "(/ (/ 8 4) 2)");
+        assert_parses_to("a or b or c", "(or (or a b) c)");
+    }
+
+    #[test]
+    fn assignment_is_right_associative() {
+        assert_parses_to("a = b = 1", "(= a (= b 1))");
+        assert_parses_to("a.b.c = d or e", "(= (. (. a b) c) (or d e))");
+    }
+
+    #[test]
+    fn calls_properties_this_and_super() {
+        assert_parses_to("f()", "(call f)");
+        assert_parses_to("f(1, a + b)(2)", "(call (call f 1 (+ a b)) 2)");
+        assert_parses_to("a.b(c).d", "(. (call (. a b) c) d)");
+        assert_parses_to("this.x", "(. this x)");
+        assert_parses_to("super.init(1)", "(call (super init) 1)");
+    }
+
+    #[test]
+    fn spans_cover_the_whole_expression() {
+        let source = "foo.bar(1, 2) + -(3)";
+        let expr = parse(source).unwrap();
+        assert_eq!(expr.span().text(source), source);
+        let Expr::Binary { left, right, .. } = &expr else {
+            panic!("expected a binary expression, got {:?}", expr);
+        };
+        assert_eq!(left.span().text(source), "foo.bar(1, 2)");
+        assert_eq!(right.span().text(source), "-(3)");
+        assert_eq!((right.span().line, right.span().column), (1, 17));
+    }
+
+    #[test]
+    fn missing_operand() {
+        let error = parse("1 +").unwrap_err();
+        assert_eq!(error.to_string(), "Expected expression, found end of file");
+        assert_eq!(error.code(), "E0101");
+        assert_eq!(error.span().start, 3);
+    }
+
+    #[test]
+    fn unclosed_group_and_call() {
+        let error = parse("(1 + 2").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Expected ')' after expression, found end of file"
+        );
+        let error = parse("f(1 2)").unwrap_err();
+        assert_eq!(error.to_string(), "Expected ')' after arguments, found '2'");
+        assert_eq!(error.span().text("f(1 2)"), "2");
+    }
+
+    #[test]
+    fn trailing_tokens() {
+        let error = parse("1 2").unwrap_err();
+        assert_eq!(error.to_string(), "Expected end of expression, found '2'");
+    }
+
+    #[test]
+    fn invalid_assignment_target() {
+        let source = "a + b = c";
+        let error = parse(source).unwrap_err();
+        assert_eq!(error.code(), "E0102");
+        assert_eq!(error.span().text(source), "a + b");
+    }
+
+    #[test]
+    fn too_many_arguments() {
+        let arguments = vec!["x"; MAX_ARGUMENTS + 1].join(", ");
+        let source = format!("f({})", arguments);
+        let error = parse(&source).unwrap_err();
+        assert_eq!(error.code(), "E0103");
+        assert_eq!(error.span().start, 2 + MAX_ARGUMENTS * 3);
+
+        let arguments = vec!["x"; MAX_ARGUMENTS].join(", ");
+        assert!(parse(&format!("f({})", arguments)).is_ok());
+    }
+
+    #[test]
+    fn errors_become_diagnostics() {
+        let diagnostic = parse("(1").unwrap_err().to_diagnostic();
+        assert_eq!(diagnostic.code, Some("E0101"));
+        assert_eq!(
+            diagnostic.labels[0].message.as_deref(),
+            Some("expected ')' after expression")
+        );
+    }
+
+    #[test]
+    fn simple_statements() {
+        assert_program(
+            "var a; var b = 1 + 2; print a; a = b;",
+            &["(var a)", "(var b (+ 1 2))", "(print a)", "(; (= a b))"],
+        );
+        assert_program("", &[]);
+    }
+
+    #[test]
+    fn blocks_and_control_flow() {
+        assert_program(
+            "{ var a = 1; { print a; } }",
+            &["(block (var a 1) (block (print a)))"],
+        );
+        assert_program(
+            "if (a) print 1; else if (b) print 2; else print 3;",
+            &["(if a (print 1) (if b (print 2) (print 3)))"],
+        );
+        // the else goes with the nearest if
+        assert_program(
+            "if (a) if (b) print 1; else print 2;",
+            &["(if a (if b (print 1) (print 2)))"],
+        );
+        assert_program(
+            "while (i < 3) i = i + 1;",
+            &["(while (< i 3) (; (= i (+ i 1))))"],
+        );
+    }
+
+    #[test]
+    fn for_loops_are_desugared() {
+        assert_program(
+            "for (var i = 0; i < 3; i = i + 1) print i;",
+            &["(block (var i 0) (while (< i 3) (block (print i) (; (= i (+ i 1))))))"],
+        );
+        assert_program("for (;;) f();", &["(while true (; (call f)))"]);
+        assert_program(
+            "for (i = 0; ; ) {}",
+            &["(block (; (= i 0)) (while true (block)))"],
+        );
+    }
+
+    #[test]
+    fn functions_and_returns() {
+        assert_program(
+            "fun add(a, b) { return a + b; } fun nothing() { return; }",
+            &[
+                "(fun add (a b) (return (+ a b)))",
+                "(fun nothing () (return))",
+            ],
+        );
+    }
+
+    #[test]
+    fn classes() {
+        assert_program(
+            "class A { init(x) { this.x = x; } get() { return this.x; } }",
+            &["(class A (fun init (x) (; (= (. this x) x))) (fun get () (return (. this x))))"],
+        );
+        assert_program(
+            "class B < A { get() { return super.get() + 1; } }",
+            &["(class B < A (fun get () (return (+ (call (super get)) 1))))"],
+        );
+        assert_program("class Empty {}", &["(class Empty)"]);
+    }
+
+    #[test]
+    fn statement_spans() {
+        let source = "var a = 1;\nfun f(x) {\n  return x;\n}\nfor (;;) print a;";
+        let statements = parse_statements(source).unwrap();
+        let texts: Vec<&str> = statements
+            .iter()
+            .map(|stmt| stmt.span().text(source))
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                "var a = 1;",
+                "fun f(x) {\n  return x;\n}",
+                "for (;;) print a;"
+            ]
+        );
+        assert_eq!(
+            (statements[1].span().line, statements[1].span().column),
+            (2, 1)
+        );
+    }
+
+    #[test]
+    fn statement_errors() {
+        let error = parse_statements("print 1").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Expected ';' after value, found end of file"
+        );
+        let error = parse_statements("var 1 = 2;").unwrap_err();
+        assert_eq!(error.to_string(), "Expected variable name, found '1'");
+        let error = parse_statements("{ print 1;").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Expected '}' after block, found end of file"
+        );
+        let error = parse_statements("class A < { }").unwrap_err();
+        assert_eq!(error.to_string(), "Expected superclass name, found '{'");
+        let error = parse_statements("if a) print 1;").unwrap_err();
+        assert_eq!(error.to_string(), "Expected '(' after 'if', found 'a'");
+    }
+
+    #[test]
+    fn too_many_parameters() {
+        let params: Vec<String> = (0..=MAX_ARGUMENTS).map(|i| format!("p{}", i)).collect();
+        let source = format!("fun f({}) {{}}", params.join(", "));
+        let error = parse_statements(&source).unwrap_err();
+        assert_eq!(error.code(), "E0104");
+        assert_eq!(error.span().text(&source), format!("p{}", MAX_ARGUMENTS));
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        // runs on a test thread, so within a 2 MiB stack
+        let group = |depth| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(parse_statements(&format!("print {};", group(MAX_DEPTH))).is_ok());
+        for source in [
+            format!("print {};\nprint 2;", group(MAX_DEPTH + 1)),
+            format!("print {}1{};\nprint 2;", "f(".repeat(500), ")".repeat(500)),
+            format!("print {}1;\nprint 2;", "-".repeat(500)),
+            format!("{}{}\nprint 2;", "{".repeat(500), "}".repeat(500)),
+            format!("{}print 1;\nprint 2;", "while (true) ".repeat(500)),
+        ] {
+            let (statements, errors) = parse_with_errors(&source);
+            assert_eq!(errors[0].code(), "E0105", "{}", errors[0]);
+            assert_eq!(errors[0].span().line, 1);
+            // parsing carries on after the statement that went too deep
+            assert_eq!(
+                statements.last().map(sexpr_stmt).as_deref(),
+                Some("(print 2)")
+            );
+        }
+    }
+
+    #[test]
+    fn reports_every_error_in_a_file() {
+        let source = "var a = ;\nprint a\nvar b = 2;\nfun f( { }\nprint b;\n";
+        assert_eq!(
+            errors_of(source),
+            vec![
+                ("Expected expression, found ';'".to_string(), 1),
+                ("Expected ';' after value, found 'var'".to_string(), 3),
+                ("Expected parameter name, found '{'".to_string(), 4),
+            ]
+        );
+        // the good declarations around the errors are kept
+        let statements: Vec<String> = parse_with_errors(source).0.iter().map(sexpr_stmt).collect();
+        assert_eq!(statements, vec!["(var b 2)", "(print b)"]);
+    }
+
+    #[test]
+    fn recovers_inside_blocks() {
+        // the block's own '}' ends the skipping
+        assert_eq!(errors_of("{ ) }\nprint 1;").len(), 1);
+
+        let source = "fun f() {\n  var = 1;\n  print 2;\n  print 3 3;\n}\nprint 4;";
+        assert_eq!(
+            errors_of(source),
+            vec![
+                ("Expected variable name, found '='".to_string(), 2),
+                ("Expected ';' after value, found '3'".to_string(), 4),
+            ]
+        );
+        let statements: Vec<String> = parse_with_errors(source).0.iter().map(sexpr_stmt).collect();
+        assert_eq!(statements, vec!["(fun f () (print 2))", "(print 4)"]);
+
+        // the class body's '}' does not end the function
+        let source = "fun f() {\n  class A { 1 }\n  print 2;\n}\nprint 3;";
+        assert_eq!(
+            errors_of(source),
+            vec![("Expected method name, found '1'".to_string(), 2)]
+        );
+        let statements: Vec<String> = parse_with_errors(source).0.iter().map(sexpr_stmt).collect();
+        assert_eq!(statements, vec!["(fun f () (print 2))", "(print 3)"]);
+    }
+
+    #[test]
+    fn stray_tokens_do_not_stall_the_parser() {
+        let (statements, errors) = parse_with_errors("} ) print 1;");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "Expected expression, found '}'");
+        assert_eq!(statements.len(), 1);
+        assert_eq!(errors_of("if (").len(), 1);
+    }
+
+    #[test]
+    fn errors_name_the_expected_token() {
+        let (_, errors) = parse_with_errors("print (1;");
+        let ParseError::UnexpectedToken {
+            expected, found, ..
+        } = &errors[0]
+        else {
+            panic!("unexpected error {:?}", errors[0]);
+        };
+        assert_eq!(*expected, Some(TokenType::RightParen));
+        assert_eq!(*found, TokenType::SemiColon);
+
+        let (_, errors) = parse_with_errors("print;");
+        assert!(matches!(
+            errors[0],
+            ParseError::UnexpectedToken {
+                expected: None,
+                found: TokenType::SemiColon,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn invalid_assignment_does_not_unwind() {
+        let source = "1 = 2; a + b = c; print 3;";
+        assert_eq!(
+            errors_of(source),
+            vec![
+                ("Invalid assignment target".to_string(), 1),
+                ("Invalid assignment target".to_string(), 1),
+            ]
+        );
+        assert_eq!(parse_with_errors(source).0.len(), 3);
+    }
+
+    #[test]
+    fn every_short_token_sequence_terminates() {
+        const PIECES: &[&str] = &[
+            "var", "x", "=", "1", ";", "{", "}", "(", ")", "fun", "class", "<", ".", ",", "if",
+            "else", "for", "return", "super", "this", "print", "+", "!",
+        ];
+        for a in PIECES {
+            for b in PIECES {
+                for c in PIECES {
+                    let source = format!("{} {} {}", a, b, c);
+                    let (tokens, _) = scan_tokens(&source);
+                    // every declaration either parses or reports an error
+                    let (statements, errors) = parse_program(&tokens);
+                    assert!(
+                        statements.len() + errors.len() <= tokens.len(),
+                        "{}",
+                        source
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use crate::ast::{Expr, Stmt};
+use crate::parser::{parse_expression, parse_program, ParseError};
+use crate::tokens::scan_tokens;
+
+/// The statements of `source` and the errors found parsing it. The source
+/// must lex without errors.
+pub(crate) fn parse_with_errors(source: &str) -> (Vec<Stmt<'_>>, Vec<ParseError>) {
+    let (tokens, errors) = scan_tokens(source);
+    assert!(errors.is_empty(), "{:?}", errors);
+    parse_program(&tokens)
+}
+
+/// The statements of `source`, which must parse without errors.
+pub(crate) fn program(source: &str) -> Vec<Stmt<'_>> {
+    let (statements, errors) = parse_with_errors(source);
+    assert!(errors.is_empty(), "{:?}", errors);
+    statements
+}
+
+/// Parses `source` as a single expression. The source must lex without
+/// errors.
+pub(crate) fn parse(source: &str) -> Result<Expr<'_>, ParseError> {
+    let (tokens, errors) = scan_tokens(source);
+    assert!(errors.is_empty(), "{:?}", errors);
+    parse_expression(&tokens)
+}
@@ -1,52 +1,223 @@
-use crate::logger::report;
-use std::{collections::HashSet, str::CharIndices};
+use crate::logger::Diagnostic;
+use crate::trivia::TriviaKind;
+use crate::xid;
+use crate::{debug, trace};
+use std::{
+    borrow::Cow, collections::HashMap, fmt, iter::FusedIterator, ops::Range, sync::LazyLock,
+};
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum Literal {
+pub enum Literal<'src> {
     // function names etc
-    Identifier(String),
-    // string values
-    Str(String),
+    Identifier(&'src str),
+    // string values, only owned when the source text had to be rewritten
+    Str(Cow<'src, str>),
     // numbers, all f64 for reasons of laziness
     Number(f64),
 }
 
+/// Where a token sits in the source.
+///
+/// `start` and `end` are byte offsets (end exclusive), `line` and `column`
+/// are 1-based and point at the first character of the token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Self {
+        Span {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
+    /// The exact source text covered by the span.
+    pub fn text<'a>(&self, source: &'a str) -> &'a str {
+        &source[self.start..self.end]
+    }
+
+    /// Byte range of the whole line the span starts on, without its line ending.
+    pub fn line_range(&self, source: &str) -> Range<usize> {
+        let line_start = source[..self.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[self.start..]
+            .find('\n')
+            .map_or(source.len(), |i| self.start + i);
+        let line_end = if source[..line_end].ends_with('\r') {
+            line_end - 1
+        } else {
+            line_end
+        };
+        line_start..line_end
+    }
+
+    /// A span from the start of `self` to the end of `end`, for syntax
+    /// covering several tokens.
+    pub fn to(self, end: Span) -> Span {
+        Span::new(self.start, end.end.max(self.end), self.line, self.column)
+    }
+
+    /// Line and column just past the last character of the span, counted the
+    /// same way as `line` and `column`.
+    pub fn end_position(&self, source: &str) -> (usize, usize) {
+        let text = self.text(source);
+        match text.rfind('\n') {
+            Some(i) => (
+                self.line + text.matches('\n').count(),
+                text[i + 1..].chars().count() + 1,
+            ),
+            None => (self.line, self.column + text.chars().count()),
+        }
+    }
+
+    /// The text of the line the span starts on, ie for error messages.
+    pub fn line_text<'a>(&self, source: &'a str) -> &'a str {
+        &source[self.line_range(source)]
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
-pub struct Token {
+pub struct Token<'src> {
     // Enum of token types
     pub token_type: TokenType,
-    // a collection of the chars of the word ie 'a', 'n', 'd'
-    pub lexeme: Option<Vec<char>>,
+    // the source text of the token ie "and", borrowed from the source
+    pub lexeme: &'src str,
     // the value itself ie if number 123 or string "hello word"
-    pub literal: Option<Literal>,
-    // line of where column was found
-    pub line: i32,
+    pub literal: Option<Literal<'src>>,
+    // where in the source the token was found
+    pub span: Span,
 }
 
-impl Token {
+impl<'src> Token<'src> {
     pub fn new(
         token_type: TokenType,
-        lexeme: Option<Vec<char>>,
-        literal: Option<Literal>,
-        line: i32,
+        lexeme: &'src str,
+        literal: Option<Literal<'src>>,
+        span: Span,
     ) -> Self {
         self::Token {
             token_type,
             literal,
             lexeme,
-            line,
+            span,
         }
     }
 
-    pub fn new_token(token_type: TokenType) -> Self {
-        Token::new(token_type, None, None, 0)
-    }
-
     // fn to_string(&self) -> String {
     //     format!("{:?} {} {}", self.token_type, self.lexeme, self.literal)
     // }
 }
 
+/// A problem found while scanning, the scanner records it and carries on with
+/// the next character so one pass reports every error in the source.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexError {
+    // a character that cannot start any token ie '@'
+    UnexpectedChar {
+        character: char,
+        span: Span,
+    },
+    // a string missing its closing quote, spans from the opening quote to the end
+    UnterminatedString {
+        span: Span,
+    },
+    // a badly formed number ie 123abc, 1.2.3 or 0x, the span points at the
+    // offending part of the literal and the reason says what is wrong with it
+    MalformedNumber {
+        lexeme: String,
+        reason: &'static str,
+        span: Span,
+    },
+    // an unknown or badly formed escape inside a string ie \q or \u{110000}
+    InvalidEscape {
+        sequence: String,
+        span: Span,
+    },
+    // a /* comment still open at the end of the source, spans its opening /*
+    UnterminatedBlockComment {
+        span: Span,
+    },
+}
+
+impl LexError {
+    pub fn span(&self) -> Span {
+        match self {
+            LexError::UnexpectedChar { span, .. }
+            | LexError::UnterminatedString { span }
+            | LexError::MalformedNumber { span, .. }
+            | LexError::InvalidEscape { span, .. }
+            | LexError::UnterminatedBlockComment { span } => *span,
+        }
+    }
+
+    /// Whether the error only means the input stopped early, e.g. a string
+    /// still waiting for its closing quote, so more input could fix it.
+    pub fn is_incomplete(&self) -> bool {
+        matches!(
+            self,
+            LexError::UnterminatedString { .. } | LexError::UnterminatedBlockComment { .. }
+        )
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            LexError::UnexpectedChar { .. } => "E0001",
+            LexError::UnterminatedString { .. } => "E0002",
+            LexError::MalformedNumber { .. } => "E0003",
+            LexError::InvalidEscape { .. } => "E0004",
+            LexError::UnterminatedBlockComment { .. } => "E0005",
+        }
+    }
+
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::error(self.to_string(), self.span()).with_code(self.code());
+        match self {
+            LexError::UnexpectedChar { .. } => {
+                diagnostic.with_primary_label("not valid at the start of a token")
+            }
+            LexError::UnterminatedString { .. } => diagnostic
+                .with_primary_label("string starts here")
+                .with_note("add a closing '\"' to end the string"),
+            LexError::MalformedNumber { reason, .. } => diagnostic
+                .with_primary_label(*reason)
+                .with_note("numbers look like 12, 12.5, 2.5e-3, 1_000, 0x1F or 0b1010"),
+            LexError::InvalidEscape { .. } => diagnostic
+                .with_primary_label("unknown escape")
+                .with_note("valid escapes are \\\" \\\\ \\n \\t \\r \\0 and \\u{XXXX}"),
+            LexError::UnterminatedBlockComment { .. } => diagnostic
+                .with_primary_label("comment starts here")
+                .with_note("block comments nest, each /* needs its own */"),
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexError::UnexpectedChar { character, .. } => {
+                write!(f, "Unexpected character '{}'", character.escape_debug())
+            }
+            LexError::UnterminatedString { .. } => write!(f, "Unterminated string"),
+            LexError::MalformedNumber { lexeme, reason, .. } => {
+                write!(f, "Malformed number literal '{}': {}", lexeme, reason)
+            }
+            LexError::InvalidEscape { sequence, .. } => {
+                write!(f, "Invalid escape sequence '{}'", sequence)
+            }
+            LexError::UnterminatedBlockComment { .. } => write!(f, "Unterminated block comment"),
+        }
+    }
+}
+
+impl std::error::Error for LexError {}
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum TokenType {
     // Single-character tokens
@@ -54,6 +225,8 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     DOT,
     MINUS,
@@ -98,330 +271,697 @@ pub enum TokenType {
     EOF,
 }
 
-// impl TokenType {}
-
-pub fn parse_chars(
-    current_char: char,
-    char_string: &mut CharIndices,
-    // current_line: mut i32,
-) -> Option<Token> {
-    // let character = current_char.as_str();
-    println!(
-        "parse_chars: current_char({}), char_string({:?})",
-        current_char, char_string
-    );
-    match current_char {
-        '(' => Some(Token::new_token(TokenType::LeftParen)),
-        ')' => Some(Token::new_token(TokenType::RightParen)),
-        '[' => Some(Token::new_token(TokenType::LeftBrace)),
-        ']' => Some(Token::new_token(TokenType::RightBrace)),
-        ',' => Some(Token::new_token(TokenType::Comma)),
-        '.' => Some(Token::new_token(TokenType::DOT)),
-        '-' => Some(Token::new_token(TokenType::MINUS)),
-        '+' => Some(Token::new_token(TokenType::PLUS)),
-        ';' => Some(Token::new_token(TokenType::SemiColon)),
-        '*' => Some(Token::new_token(TokenType::STAR)),
-        '!' => {
-            let next_character = char_string.next();
-            match next_character {
-                Some((_, next_char)) => {
-                    if next_char.to_string() == "=" {
-                        // '!='
-                        Some(Token::new_token(TokenType::BangEqual))
-                    } else {
-                        Some(Token::new_token(TokenType::BANG))
-                    }
-                }
-                None => Some(Token::new_token(TokenType::BANG)),
+/// Every reserved word of the language, adding a keyword is one more row here.
+const KEYWORDS: &[(&str, TokenType)] = &[
+    ("and", TokenType::AND),
+    ("class", TokenType::CLASS),
+    ("else", TokenType::ELSE),
+    ("false", TokenType::FALSE),
+    ("for", TokenType::FOR),
+    ("fun", TokenType::FUN),
+    ("if", TokenType::IF),
+    ("nil", TokenType::NIL),
+    ("or", TokenType::OR),
+    ("print", TokenType::PRINT),
+    ("return", TokenType::RETURN),
+    ("super", TokenType::SUPER),
+    ("this", TokenType::THIS),
+    ("true", TokenType::TRUE),
+    ("var", TokenType::VAR),
+    ("while", TokenType::While),
+];
+
+static KEYWORD_MAP: LazyLock<HashMap<&'static str, TokenType>> =
+    LazyLock::new(|| KEYWORDS.iter().copied().collect());
+
+impl TokenType {
+    /// Returns the keyword token type for `word`, or None for plain identifiers.
+    pub fn keyword(word: &str) -> Option<TokenType> {
+        KEYWORD_MAP.get(word).copied()
+    }
+}
+
+/// Walks the source text one character at a time.
+///
+/// `start` and `current` are byte offsets into `source`: `start` marks the
+/// first character of the lexeme being scanned and `current` the character
+/// that will be consumed next. Lookahead goes through `peek`, `peek_next`
+/// and `match_char`, so no branch consumes a character it does not use.
+///
+/// Tokens borrow their lexemes from `source` instead of copying them.
+pub struct Scanner<'src> {
+    source: &'src str,
+    start: usize,
+    current: usize,
+    line: usize,
+    column: usize,
+    // position of the character at `start`
+    start_line: usize,
+    start_column: usize,
+    // set once the EOF token has been produced
+    done: bool,
+    // an error in the token just produced, returned by the next step
+    token_error: Option<LexError>,
+}
+
+/// What a single call to `Scanner::step` consumed, the text of each step is
+/// the source between `start` and `current`.
+pub(crate) enum Step<'src> {
+    Token(Token<'src>),
+    Trivia(TriviaKind),
+    // the consumed text did not form a token
+    Error(LexError),
+    // a problem with the token of the previous step, which was still
+    // emitted ie a string with a bad escape, consumes nothing
+    TokenError(LexError),
+}
+
+impl<'src> Scanner<'src> {
+    pub fn new(source: &'src str) -> Self {
+        Scanner {
+            source,
+            start: 0,
+            current: 0,
+            line: 1,
+            column: 1,
+            start_line: 1,
+            start_column: 1,
+            done: false,
+            token_error: None,
+        }
+    }
+
+    /// A scanner that starts at byte `offset` of `source`, whose character is
+    /// at `line` and `column`. `offset` must be where a token or trivia
+    /// starts, i.e. not inside a string or comment.
+    pub(crate) fn resume(source: &'src str, offset: usize, line: usize, column: usize) -> Self {
+        Scanner {
+            source,
+            start: offset,
+            current: offset,
+            line,
+            column,
+            start_line: line,
+            start_column: column,
+            done: false,
+            token_error: None,
+        }
+    }
+
+    /// Collects every token and error, see `scan_tokens`.
+    pub fn scan_tokens(self) -> (Vec<Token<'src>>, Vec<LexError>) {
+        let mut tokens: Vec<Token<'src>> = Vec::new();
+        let mut errors: Vec<LexError> = Vec::new();
+
+        trace!("source:{}", self.source);
+        for result in self {
+            match result {
+                Ok(token) => tokens.push(token),
+                Err(error) => errors.push(error),
             }
         }
-        '=' => {
-            let next_character = char_string.next();
-            match next_character {
-                Some((_, next_char)) => {
-                    if next_char.to_string() == "=" {
-                        // '=='
-                        Some(Token::new_token(TokenType::EqualEqual))
-                    } else {
-                        Some(Token::new_token(TokenType::EQUAL))
-                    }
-                }
-                None => Some(Token::new_token(TokenType::EQUAL)),
+
+        (tokens, errors)
+    }
+
+    /// Consumes the next token, piece of trivia or erroneous lexeme. The last
+    /// step is the EOF token, after which None is returned.
+    pub(crate) fn step(&mut self) -> Option<Step<'src>> {
+        if let Some(error) = self.token_error.take() {
+            return Some(Step::TokenError(error));
+        }
+        if self.done {
+            return None;
+        }
+
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = self.column;
+
+        if self.is_at_end() {
+            debug!("found the end return EOF");
+            self.done = true;
+            return Some(Step::Token(self.make_token(TokenType::EOF)));
+        }
+
+        let character = self.advance();
+        trace!("character:{:?},", character);
+
+        // whitespace only separates tokens
+        if is_whitespace(character) {
+            return Some(Step::Trivia(self.walk_whitespace(character)));
+        }
+
+        Some(match self.parse_chars(character) {
+            Ok(Some(token)) => Step::Token(token),
+            // parse_chars only skips over comments
+            Ok(None) if self.lexeme().starts_with("//") => Step::Trivia(TriviaKind::LineComment),
+            Ok(None) => Step::Trivia(TriviaKind::BlockComment),
+            Err(error) => Step::Error(error),
+        })
+    }
+
+    /// Consumes a line break, "\r\n" counting as one, or a run of other
+    /// whitespace.
+    fn walk_whitespace(&mut self, current_char: char) -> TriviaKind {
+        if current_char == '\n' || (current_char == '\r' && self.match_char('\n')) {
+            return TriviaKind::Newline;
+        }
+        while let Some(next_char) = self.peek() {
+            let line_break =
+                next_char == '\n' || (next_char == '\r' && self.peek_next() == Some('\n'));
+            if line_break || !is_whitespace(next_char) {
+                break;
             }
+            self.advance();
         }
-        '>' => {
-            let next_character = char_string.next();
-            match next_character {
-                Some((_, next_char)) => {
-                    if next_char.to_string() == "=" {
-                        // '!='
-                        Some(Token::new_token(TokenType::GreatEqual))
-                    } else {
-                        Some(Token::new_token(TokenType::GREATER))
-                    }
-                }
-                None => Some(Token::new_token(TokenType::GREATER)),
+        TriviaKind::Whitespace
+    }
+
+    /// Byte offset, line and column of `current`, to build a span with
+    /// `span_from` once more characters have been consumed.
+    fn mark(&self) -> (usize, usize, usize) {
+        (self.current, self.line, self.column)
+    }
+
+    fn span_from(&self, (start, line, column): (usize, usize, usize)) -> Span {
+        Span::new(start, self.current, line, column)
+    }
+
+    /// Span of the lexeme between `start` and `current`.
+    pub(crate) fn current_span(&self) -> Span {
+        Span::new(self.start, self.current, self.start_line, self.start_column)
+    }
+
+    /// The source text between `start` and `current`.
+    pub(crate) fn lexeme(&self) -> &'src str {
+        &self.source[self.start..self.current]
+    }
+
+    fn make_token(&self, token_type: TokenType) -> Token<'src> {
+        Token::new(token_type, self.lexeme(), None, self.current_span())
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.current >= self.source.len()
+    }
+
+    fn advance(&mut self) -> char {
+        let current_char = self
+            .peek()
+            .expect("advance should not be called at the end of the source");
+        self.current += current_char.len_utf8();
+        match current_char {
+            '\n' => {
+                self.line += 1;
+                self.column = 1;
             }
+            // the '\r' of a "\r\n" line ending takes up no column
+            '\r' if self.peek() == Some('\n') => {}
+            _ => self.column += 1,
         }
-        '<' => {
-            let next_character = char_string.next();
-            match next_character {
-                Some((_, next_char)) => {
-                    if next_char.to_string() == "=" {
-                        // '!='
-                        Some(Token::new_token(TokenType::LessEqual))
-                    } else {
-                        Some(Token::new_token(TokenType::LESS))
-                    }
+        current_char
+    }
+
+    /// Returns the next character without consuming it.
+    fn peek(&self) -> Option<char> {
+        self.source[self.current..].chars().next()
+    }
+
+    /// Returns the character after the next one without consuming anything.
+    fn peek_next(&self) -> Option<char> {
+        self.source[self.current..].chars().nth(1)
+    }
+
+    /// Consumes the next character only if it is `expected`.
+    fn match_char(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Scans the token starting with `current_char`, Ok(None) means the
+    /// characters were consumed without producing a token ie a comment.
+    pub fn parse_chars(&mut self, current_char: char) -> Result<Option<Token<'src>>, LexError> {
+        trace!(
+            "parse_chars: current_char({}), line({}), column({})",
+            current_char,
+            self.line,
+            self.column
+        );
+        match current_char {
+            '(' => Ok(Some(self.make_token(TokenType::LeftParen))),
+            ')' => Ok(Some(self.make_token(TokenType::RightParen))),
+            '{' => Ok(Some(self.make_token(TokenType::LeftBrace))),
+            '}' => Ok(Some(self.make_token(TokenType::RightBrace))),
+            '[' => Ok(Some(self.make_token(TokenType::LeftBracket))),
+            ']' => Ok(Some(self.make_token(TokenType::RightBracket))),
+            ',' => Ok(Some(self.make_token(TokenType::Comma))),
+            '.' => Ok(Some(self.make_token(TokenType::DOT))),
+            '-' => Ok(Some(self.make_token(TokenType::MINUS))),
+            '+' => Ok(Some(self.make_token(TokenType::PLUS))),
+            ';' => Ok(Some(self.make_token(TokenType::SemiColon))),
+            '*' => Ok(Some(self.make_token(TokenType::STAR))),
+            '!' => {
+                if self.match_char('=') {
+                    Ok(Some(self.make_token(TokenType::BangEqual)))
+                } else {
+                    Ok(Some(self.make_token(TokenType::BANG)))
                 }
-                None => Some(Token::new_token(TokenType::LESS)),
             }
-        }
-        '/' => {
-            let next_character = char_string.next();
-            match next_character {
-                Some((_, next_char)) => {
-                    if next_char.to_string() == "/" {
-                        loop {
-                            let (_, following_char) = char_string.next()?;
-                            if following_char == '\n' {
-                                // current_line += 1;
-                                return None;
-                            }
+            '=' => {
+                if self.match_char('=') {
+                    Ok(Some(self.make_token(TokenType::EqualEqual)))
+                } else {
+                    Ok(Some(self.make_token(TokenType::EQUAL)))
+                }
+            }
+            '>' => {
+                if self.match_char('=') {
+                    Ok(Some(self.make_token(TokenType::GreatEqual)))
+                } else {
+                    Ok(Some(self.make_token(TokenType::GREATER)))
+                }
+            }
+            '<' => {
+                if self.match_char('=') {
+                    Ok(Some(self.make_token(TokenType::LessEqual)))
+                } else {
+                    Ok(Some(self.make_token(TokenType::LESS)))
+                }
+            }
+            '/' => {
+                if self.match_char('/') {
+                    // the newline is left for scan_tokens so the line count stays right
+                    while let Some(next_char) = self.peek() {
+                        if next_char == '\n'
+                            || (next_char == '\r' && self.peek_next() == Some('\n'))
+                        {
+                            break;
                         }
-                    } else {
-                        Some(Token::new_token(TokenType::SLASH))
+                        self.advance();
                     }
+                    Ok(None)
+                } else if self.match_char('*') {
+                    self.walk_block_comment()?;
+                    Ok(None)
+                } else {
+                    Ok(Some(self.make_token(TokenType::SLASH)))
                 }
-                None => Some(Token::new_token(TokenType::SLASH)),
             }
-        }
-        current_char if current_char.is_alphabetic() => {
-            println!("found is_alphabetic {}", current_char);
-            keyword_match(current_char, char_string)
-        }
-        current_char if current_char.is_numeric() => {
-            println!("found is_numeric {}", current_char);
-            let chars = walk_to_next_whitespace(char_string, current_char);
-
-            let liter_string: String = String::from_iter(chars.clone().into_iter());
-
-            match liter_string.parse::<f64>() {
-                Ok(v) => Some(Token::new(
-                    TokenType::NUMBER,
-                    Some(chars),
-                    Some(Literal::Number(v)),
-                    0,
-                )),
-                Err(e) => {
-                    println!("Trouble Parsing number literal: {:?}", e);
-
-                    Some(Token::new(
-                        TokenType::IDENTIFIER,
-                        Some(chars),
-                        Some(Literal::Str(liter_string)),
-                        0,
-                    ))
+            current_char if is_identifier_start(current_char) => {
+                trace!("found identifier start {}", current_char);
+                self.walk_identifier();
+                Ok(Some(identifier_token(self.lexeme(), self.current_span())))
+            }
+            current_char if current_char.is_ascii_digit() => {
+                trace!("found digit {}", current_char);
+                match self.walk_number(current_char) {
+                    Ok(v) => Ok(Some(Token::new(
+                        TokenType::NUMBER,
+                        self.lexeme(),
+                        Some(Literal::Number(v)),
+                        self.current_span(),
+                    ))),
+                    // still a number as far as the parser is concerned, the
+                    // zero is never used as the error gets reported
+                    Err((reason, span)) => {
+                        self.token_error = Some(LexError::MalformedNumber {
+                            lexeme: self.lexeme().to_string(),
+                            reason,
+                            span,
+                        });
+                        Ok(Some(Token::new(
+                            TokenType::NUMBER,
+                            self.lexeme(),
+                            Some(Literal::Number(0.0)),
+                            self.current_span(),
+                        )))
+                    }
                 }
             }
+            '"' => {
+                let value = self.walk_string()?;
+                Ok(Some(Token::new(
+                    TokenType::STRING,
+                    self.lexeme(),
+                    Some(Literal::Str(value)),
+                    self.current_span(),
+                )))
+            }
+            _ => Err(LexError::UnexpectedChar {
+                character: current_char,
+                span: self.current_span(),
+            }),
         }
-        '"' => {
-            let chars = walk_to_next_quote(char_string);
-            let literal_string: String = String::from_iter(chars.clone());
-            Some(Token::new(
-                TokenType::STRING,
-                Some(chars),
-                Some(Literal::Str(literal_string)),
-                0,
-            ))
+    }
+
+    /// Consumes the identifier characters following the one at `start`.
+    fn walk_identifier(&mut self) {
+        while self.peek().is_some_and(is_identifier_char) {
+            self.advance();
         }
-        _ => None,
     }
-}
 
-fn keyword_match(current_char: char, char_string: &mut CharIndices) -> Option<Token> {
-    match current_char {
-        'a' => match_on_keywords(char_string, &["and"], &[TokenType::AND], current_char),
-        'c' => match_on_keywords(char_string, &["class"], &[TokenType::CLASS], current_char),
-        'e' => match_on_keywords(char_string, &["else"], &[TokenType::ELSE], current_char),
-        'f' => match_on_keywords(
-            char_string,
-            &["for", "fun", "false"],
-            &[TokenType::FOR, TokenType::FUN, TokenType::FALSE],
-            current_char,
-        ),
-        'i' => match_on_keywords(char_string, &["if"], &[TokenType::IF], current_char),
-        'n' => match_on_keywords(char_string, &["nil"], &[TokenType::NIL], current_char),
-        'o' => match_on_keywords(char_string, &["or"], &[TokenType::OR], current_char),
-        'p' => match_on_keywords(char_string, &["print"], &[TokenType::PRINT], current_char),
-        'r' => match_on_keywords(char_string, &["return"], &[TokenType::RETURN], current_char),
-        's' => match_on_keywords(char_string, &["super"], &[TokenType::SUPER], current_char),
-        't' => match_on_keywords(
-            char_string,
-            &["this", "true"],
-            &[TokenType::THIS, TokenType::TRUE],
-            current_char,
-        ),
-        'v' => match_on_keywords(char_string, &["var"], &[TokenType::VAR], current_char),
-        'w' => match_on_keywords(char_string, &["while"], &[TokenType::While], current_char),
-        _ => Some(walk_to_next_whitespace_identifier(
-            char_string,
-            current_char,
-        )),
+    /// Consumes digits in `radix` with `_` separators between them, returning
+    /// the span of a separator left dangling at the end of the run.
+    fn walk_digits(&mut self, radix: u32) -> Option<Span> {
+        let mut dangling = None;
+        while let Some(c) = self.peek().filter(|c| c.is_digit(radix) || *c == '_') {
+            let position = self.mark();
+            self.advance();
+            dangling = (c == '_').then(|| self.span_from(position));
+        }
+        dangling
     }
-}
 
-fn walk_to_next_whitespace(source_chars: &mut CharIndices, current_char: char) -> Vec<char> {
-    let mut chars = vec![current_char];
-    for e in source_chars.by_ref() {
-        match e.1 {
-            '\n' => break,
-            '\t' => break,
-            ' ' => break,
-            _ => {
-                chars.push(e.1);
-                continue;
+    /// Consumes a number literal whose first digit is at `start` and returns
+    /// its value. Accepted forms are
+    ///
+    /// - decimals with an optional fraction and exponent: `12`, `12.5`, `1e10`, `2.5E-3`
+    /// - hex and binary integers: `0x1F`, `0b1010`
+    /// - `_` separators between digits of any of these: `1_000_000`
+    ///
+    /// A dot without a digit after it is left alone, so `123.` is a number
+    /// followed by a DOT.
+    ///
+    /// On a malformed literal the rest of the word is consumed so scanning
+    /// carries on after it, and the error names the offending part.
+    fn walk_number(&mut self, first_digit: char) -> Result<f64, (&'static str, Span)> {
+        const DANGLING_SEPARATOR: &str = "digit separators must be followed by a digit";
+        let mut error = None;
+
+        let radix = match (first_digit, self.peek()) {
+            ('0', Some('x' | 'X')) => 16,
+            ('0', Some('b' | 'B')) => 2,
+            _ => 10,
+        };
+
+        if radix == 10 {
+            if let Some(span) = self.walk_digits(10) {
+                error.get_or_insert((DANGLING_SEPARATOR, span));
+            }
+
+            if self.peek() == Some('.') && self.peek_next().is_some_and(|c| c.is_ascii_digit()) {
+                self.advance();
+                if let Some(span) = self.walk_digits(10) {
+                    error.get_or_insert((DANGLING_SEPARATOR, span));
+                }
+            }
+
+            if matches!(self.peek(), Some('e' | 'E')) {
+                let exponent = self.mark();
+                self.advance();
+                if matches!(self.peek(), Some('+' | '-')) {
+                    self.advance();
+                }
+                if self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    if let Some(span) = self.walk_digits(10) {
+                        error.get_or_insert((DANGLING_SEPARATOR, span));
+                    }
+                } else {
+                    error.get_or_insert((
+                        "expected digits in the exponent",
+                        self.span_from(exponent),
+                    ));
+                }
+            }
+        } else {
+            // the radix prefix
+            self.advance();
+            let digits_start = self.current;
+            let dangling = self.walk_digits(radix);
+            if self.source[digits_start..self.current]
+                .chars()
+                .all(|c| c == '_')
+            {
+                let reason = if radix == 16 {
+                    "expected hex digits after '0x'"
+                } else {
+                    "expected binary digits after '0b'"
+                };
+                error.get_or_insert((reason, self.current_span()));
+            } else if let Some(span) = dangling {
+                error.get_or_insert((DANGLING_SEPARATOR, span));
             }
         }
-    }
 
-    chars
-}
+        // a literal running straight into letters, digits or a second fraction
+        let tail = self.mark();
+        loop {
+            match self.peek() {
+                Some(c) if is_identifier_char(c) => self.advance(),
+                Some('.') if self.peek_next().is_some_and(|c| c.is_ascii_digit()) => self.advance(),
+                _ => break,
+            };
+        }
+        let tail_text = &self.source[tail.0..self.current];
+        if let Some(first) = tail_text.chars().next() {
+            let reason = match (first, radix) {
+                ('.', 10) => "a number can only have one fractional part",
+                ('.', _) => "hex and binary literals cannot have a fractional part",
+                (c, 2) if c.is_ascii_digit() => "invalid digit for a binary literal",
+                _ => "unexpected characters after the number",
+            };
+            error.get_or_insert((reason, self.span_from(tail)));
+        }
 
-fn walk_to_next_whitespace_identifier(source_chars: &mut CharIndices, current_char: char) -> Token {
-    let chars = walk_to_next_whitespace(source_chars, current_char);
-    let literal: String = String::from_iter(chars.clone());
-    Token {
-        token_type: TokenType::IDENTIFIER,
-        lexeme: Some(chars),
-        literal: Some(Literal::Identifier(literal)),
-        line: 0,
+        if let Some(error) = error {
+            return Err(error);
+        }
+
+        let lexeme = self.lexeme();
+        if radix == 10 {
+            // only copied when there are separators to take out
+            let digits = if lexeme.contains('_') {
+                Cow::Owned(lexeme.replace('_', ""))
+            } else {
+                Cow::Borrowed(lexeme)
+            };
+            digits
+                .parse::<f64>()
+                .map_err(|_| ("not a valid number", self.current_span()))
+        } else {
+            // skip the prefix, the digits were checked while scanning
+            Ok(lexeme[2..]
+                .chars()
+                .filter(|&c| c != '_')
+                .fold(0_f64, |value, c| {
+                    value * radix as f64 + c.to_digit(radix).unwrap_or(0) as f64
+                }))
+        }
     }
-}
 
-fn walk_to_next_quote(source_chars: &mut CharIndices) -> Vec<char> {
-    let mut chars = Vec::new();
-    for e in source_chars.by_ref() {
-        match e.1 {
-            '"' => break,
-            _ => {
-                chars.push(e.1);
-                continue;
+    /// Consumes a block comment whose opening `/*` is at `start`, including
+    /// any comments nested inside it.
+    fn walk_block_comment(&mut self) -> Result<(), LexError> {
+        let mut depth = 1;
+        while depth > 0 {
+            match (self.peek(), self.peek_next()) {
+                (None, _) => {
+                    return Err(LexError::UnterminatedBlockComment {
+                        span: Span::new(
+                            self.start,
+                            self.start + 2,
+                            self.start_line,
+                            self.start_column,
+                        ),
+                    })
+                }
+                (Some('/'), Some('*')) => {
+                    self.advance();
+                    self.advance();
+                    depth += 1;
+                }
+                (Some('*'), Some('/')) => {
+                    self.advance();
+                    self.advance();
+                    depth -= 1;
+                }
+                _ => {
+                    self.advance();
+                }
             }
         }
+        Ok(())
     }
-    chars
-}
 
-/// .Returns Some(index) of matching keyword or None
-fn walk_keywords(source_chars: &mut CharIndices, keywords: &[&str]) -> Option<usize> {
-    let keywords_iter = keywords.iter().map(|kw| kw.char_indices());
-
-    // [['t', 'h', 'i', 's'], ['t', 'r', 'u', 'e']]
-    // [['t 'h, 'i', 's'], ['t', 'r', 'u', 'e']]
-    // need to iterate on first
-    // on each iteration get the current index of each vec
-    // and return a vec with
-    // 0: ['t', 't'] => t => true
-    // 1: ['h', 'r']
-    // 2: ['i', 'u']
-    // 3: ['s', 'e']
-    // 4: [None, None]
-    let amount_words: Vec<usize> = (0..keywords.len()).collect();
-    let mut matching_words: HashSet<usize> = HashSet::from_iter(amount_words);
-
-    for (loop_index, current_source_char) in source_chars.by_ref() {
-        println!(
-            "current_source_char {:?} {:?}",
-            current_source_char, matching_words
-        );
-        if current_source_char.is_whitespace() {
-            break;
-        }
+    /// Consumes a string literal up to and including its closing quote and
+    /// returns its value with escape sequences replaced.
+    ///
+    /// The value borrows the text between the quotes and is only copied once
+    /// an escape sequence is found. The lexeme keeps the raw text.
+    fn walk_string(&mut self) -> Result<Cow<'src, str>, LexError> {
+        let contents_start = self.current;
+        // copied from the source at the first escape
+        let mut cooked: Option<String> = None;
+        let mut invalid_escape: Option<LexError> = None;
 
-        for (keywords_iter_index, mut keyword_char_iter) in keywords_iter.clone().enumerate() {
-            match keyword_char_iter.nth(loop_index) {
-                Some((_, keyword_char)) => {
-                    if keyword_char != current_source_char {
-                        // not matching_words
-                        matching_words.remove(&keywords_iter_index);
+        loop {
+            match self.peek() {
+                None => {
+                    return Err(LexError::UnterminatedString {
+                        span: self.current_span(),
+                    })
+                }
+                Some('"') => break,
+                Some('\\') => {
+                    let cooked = cooked.get_or_insert_with(|| {
+                        self.source[contents_start..self.current].to_string()
+                    });
+                    match self.walk_escape() {
+                        Ok(c) => cooked.push(c),
+                        // keep the escape as written
+                        Err(error) => {
+                            cooked.push_str(error.span().text(self.source));
+                            invalid_escape.get_or_insert(error);
+                        }
                     }
                 }
-                None => {
-                    println!("loop_index {:?}", loop_index);
-                    matching_words.remove(&keywords_iter_index);
+                Some(_) => {
+                    let c = self.advance();
+                    if let Some(cooked) = cooked.as_mut() {
+                        cooked.push(c);
+                    }
                 }
-            };
+            }
+        }
+
+        let contents = &self.source[contents_start..self.current];
+        // the closing quote
+        self.advance();
+
+        // the string is still a token, the escape is reported on its own
+        self.token_error = invalid_escape;
+        match cooked {
+            Some(cooked) => Ok(Cow::Owned(cooked)),
+            None => Ok(Cow::Borrowed(contents)),
         }
     }
 
-    matching_words.iter().next().copied()
-}
+    /// Consumes an escape sequence starting at the backslash and returns the
+    /// character it stands for.
+    fn walk_escape(&mut self) -> Result<char, LexError> {
+        let escape = self.mark();
+        self.advance();
 
-fn match_on_keywords(
-    source_chars: &mut CharIndices,
-    keywords: &[&str],
-    token_types: &[TokenType],
-    current_char: char,
-) -> Option<Token> {
-    let word_token = walk_to_next_whitespace_identifier(source_chars, current_char);
-    let cloned_word_token = word_token.clone();
-    let word_lexeme = word_token.lexeme?;
-    let word_chars_string = String::from_iter(word_lexeme.into_iter());
-    let mut word_chars = word_chars_string.char_indices();
-
-    if let Some(index) = walk_keywords(&mut word_chars, keywords) {
-        if let Some(t) = token_types.get(index) {
-            let lexeme: Option<Vec<char>> = keywords.get(index).map(|word| {
-                let chars: Vec<char> = word.chars().into_iter().collect();
-                chars
-            });
-
-            Some(Token {
-                token_type: *t,
-                lexeme,
-                line: 0,
-                literal: None,
-            })
-        } else {
-            Some(cloned_word_token)
+        let escaped = match self.peek() {
+            Some('"') => Some('"'),
+            Some('\\') => Some('\\'),
+            Some('n') => Some('\n'),
+            Some('t') => Some('\t'),
+            Some('r') => Some('\r'),
+            Some('0') => Some('\0'),
+            Some('u') => {
+                self.advance();
+                return self
+                    .walk_unicode_escape()
+                    .ok_or_else(|| LexError::InvalidEscape {
+                        sequence: self.source[escape.0..self.current].to_string(),
+                        span: self.span_from(escape),
+                    });
+            }
+            // the string is unterminated, which is reported instead
+            None => None,
+            // leave a line break to the string
+            Some('\n') => None,
+            Some(_) => {
+                self.advance();
+                None
+            }
+        };
+
+        match escaped {
+            Some(c) => {
+                self.advance();
+                Ok(c)
+            }
+            None => Err(LexError::InvalidEscape {
+                sequence: self.source[escape.0..self.current].to_string(),
+                span: self.span_from(escape),
+            }),
+        }
+    }
+
+    /// Consumes the `{XXXX}` part of a `\u{XXXX}` escape, one to six hex digits
+    /// naming a Unicode scalar value.
+    fn walk_unicode_escape(&mut self) -> Option<char> {
+        if !self.match_char('{') {
+            return None;
         }
-    } else {
-        Some(cloned_word_token)
+        let digits_start = self.current;
+        while self.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+            self.advance();
+        }
+        let digits = &self.source[digits_start..self.current];
+        if !self.match_char('}') || digits.is_empty() || digits.len() > 6 {
+            return None;
+        }
+        u32::from_str_radix(digits, 16)
+            .ok()
+            .and_then(char::from_u32)
     }
 }
 
-pub fn scan_tokens(source: String) -> Vec<Token> {
-    let mut tokens: Vec<Token> = Vec::new();
-    let current_line: i32 = 0;
-
-    let mut source_iterator = source.char_indices();
-    println!("source:{}, source_iterator: {:?}", source, source_iterator);
-    loop {
-        let character = source_iterator.next();
-        println!("character:{:?},", character);
-
-        match character {
-            Some((_ind, char)) => {
-                let token = parse_chars(char, &mut source_iterator);
-                match token {
-                    Some(token) => tokens.push(token),
-                    None => report(
-                        current_line,
-                        "".to_string(),
-                        "Unidentified char".to_string(),
-                    ),
-                }
-            }
-            None => {
-                println!("found the end return EOF");
-                tokens.push(Token::new_token(TokenType::EOF));
-                break;
+/// Scans lazily, one token or error per call, skipping whitespace and
+/// comments. The last item is the EOF token.
+impl<'src> Iterator for Scanner<'src> {
+    type Item = Result<Token<'src>, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.step()? {
+                Step::Token(token) => return Some(Ok(token)),
+                Step::Trivia(_) => continue,
+                Step::Error(error) | Step::TokenError(error) => return Some(Err(error)),
             }
         }
     }
+}
 
-    tokens
+impl FusedIterator for Scanner<'_> {}
+
+/// Trivia between tokens: Unicode White_Space, which covers '\r' and
+/// non-breaking spaces, plus the byte order mark some editors write at the
+/// start of a file.
+fn is_whitespace(c: char) -> bool {
+    c.is_whitespace() || c == '\u{FEFF}'
+}
+
+/// Identifiers follow the UAX #31 default syntax, `XID_Start XID_Continue*`,
+/// with `_` also allowed to start one. So names can be written in any
+/// script (`größe`, `число`, `変数`), while punctuation, symbols and emoji
+/// cannot appear in them. Identifiers are compared code point by code point:
+/// no normalization is applied, so a precomposed `é` and `e` followed by a
+/// combining acute accent are different names.
+fn is_identifier_start(c: char) -> bool {
+    c == '_' || xid::is_xid_start(c)
+}
+
+fn is_identifier_char(c: char) -> bool {
+    xid::is_xid_continue(c)
+}
+
+/// Builds a keyword token when the word is reserved, otherwise an identifier.
+fn identifier_token(word: &str, span: Span) -> Token<'_> {
+    match TokenType::keyword(word) {
+        Some(token_type) => Token::new(token_type, word, None, span),
+        None => Token::new(
+            TokenType::IDENTIFIER,
+            word,
+            Some(Literal::Identifier(word)),
+            span,
+        ),
+    }
+}
+
+/// Scans the whole source, returning every token (ending with EOF) together
+/// with the errors found along the way.
+pub fn scan_tokens(source: &str) -> (Vec<Token<'_>>, Vec<LexError>) {
+    Scanner::new(source).scan_tokens()
 }
 
 #[cfg(test)]
@@ -433,9 +973,10 @@ mod tests {
     use super::*;
 
     fn assert_scanner_results(source: &str, mut expected_token: Vec<Token>) {
-        let result = scan_tokens(source.to_string());
+        let (result, errors) = scan_tokens(source);
+        assert_eq!(errors, vec![]);
 
-        let mut eof_token = vec![Token::new_token(TokenType::EOF)];
+        let mut eof_token = vec![Token::new(TokenType::EOF, "", None, Span::default())];
         expected_token.append(&mut eof_token);
 
         println!("expected the following {:?}", expected_token);
@@ -443,42 +984,92 @@ mod tests {
 
         assert_eq!(result.len(), expected_token.len());
         for (index, token) in expected_token.into_iter().enumerate() {
-            let result_token: &Token = result.get(index).unwrap();
-            assert_eq!(*result_token, token);
+            // spans are covered by their own tests below
+            let result_token = Token {
+                span: Span::default(),
+                ..result.get(index).unwrap().clone()
+            };
+            assert_eq!(result_token, token);
         }
     }
 
+    fn token_types_of(source: &str) -> Vec<TokenType> {
+        scan_tokens(source)
+            .0
+            .into_iter()
+            .map(|token| token.token_type)
+            .collect()
+    }
+
+    fn spans_of(source: &str) -> Vec<(TokenType, Span)> {
+        scan_tokens(source)
+            .0
+            .into_iter()
+            .map(|token| (token.token_type, token.span))
+            .collect()
+    }
+
     #[test]
     fn single_level_equals() {
-        assert_scanner_results("=", vec![Token::new_token(TokenType::EQUAL)]);
+        assert_scanner_results(
+            "=",
+            vec![Token::new(TokenType::EQUAL, "=", None, Span::default())],
+        );
     }
 
     #[test]
     fn single_level_greater() {
-        assert_scanner_results(">", vec![Token::new_token(TokenType::GREATER)]);
+        assert_scanner_results(
+            ">",
+            vec![Token::new(TokenType::GREATER, ">", None, Span::default())],
+        );
     }
 
     #[test]
     fn single_level_left_bracket() {
-        assert_scanner_results("[", vec![Token::new_token(TokenType::LeftBrace)]);
+        assert_scanner_results(
+            "[",
+            vec![Token::new(
+                TokenType::LeftBracket,
+                "[",
+                None,
+                Span::default(),
+            )],
+        );
     }
 
     #[test]
     fn double_level_equal() {
-        assert_scanner_results(">=", vec![Token::new_token(TokenType::GreatEqual)]);
+        assert_scanner_results(
+            ">=",
+            vec![Token::new(
+                TokenType::GreatEqual,
+                ">=",
+                None,
+                Span::default(),
+            )],
+        );
     }
 
     #[test]
     fn comments() {
         assert_scanner_results(
             "// hello i am a comment \n!=",
-            vec![Token::new_token(TokenType::BangEqual)],
+            vec![Token::new(
+                TokenType::BangEqual,
+                "!=",
+                None,
+                Span::default(),
+            )],
         )
     }
 
     #[test]
     fn division() {
-        assert_scanner_results("/", vec![Token::new_token(TokenType::SLASH)])
+        assert_scanner_results(
+            "/",
+            vec![Token::new(TokenType::SLASH, "/", None, Span::default())],
+        )
     }
 
     #[test]
@@ -487,9 +1078,9 @@ mod tests {
             "hello",
             vec![Token {
                 token_type: TokenType::IDENTIFIER,
-                lexeme: Some(vec!['h', 'e', 'l', 'l', 'o']),
-                line: 0,
-                literal: Some(Literal::Identifier("hello".to_string())),
+                lexeme: "hello",
+                span: Span::default(),
+                literal: Some(Literal::Identifier("hello")),
             }],
         )
     }
@@ -501,8 +1092,8 @@ mod tests {
             vec![Token {
                 token_type: TokenType::AND,
                 literal: None,
-                lexeme: Some(vec!['a', 'n', 'd']),
-                line: 0,
+                lexeme: "and",
+                span: Span::default(),
             }],
         )
     }
@@ -512,9 +1103,9 @@ mod tests {
             "\"and\"",
             vec![Token {
                 token_type: TokenType::STRING,
-                lexeme: Some(vec!['a', 'n', 'd']),
-                literal: Some(Literal::Str("and".to_string())),
-                line: 0,
+                lexeme: "\"and\"",
+                literal: Some(Literal::Str("and".into())),
+                span: Span::default(),
             }],
         )
     }
@@ -524,9 +1115,9 @@ mod tests {
             "123",
             vec![Token {
                 token_type: TokenType::NUMBER,
-                lexeme: Some(vec!['1', '2', '3']),
+                lexeme: "123",
                 literal: Some(Literal::Number(123_f64)),
-                line: 0,
+                span: Span::default(),
             }],
         )
     }
@@ -536,50 +1127,57 @@ mod tests {
             "123.123",
             vec![Token {
                 token_type: TokenType::NUMBER,
-                lexeme: Some(vec!['1', '2', '3', '.', '1', '2', '3']),
+                lexeme: "123.123",
                 literal: Some(Literal::Number(123.123)),
-                line: 0,
+                span: Span::default(),
             }],
         )
     }
     #[test]
     fn number_with_multiple_decimals() {
-        assert_scanner_results(
-            "123.123.123",
-            vec![Token {
-                token_type: TokenType::IDENTIFIER,
-                lexeme: Some(vec!['1', '2', '3', '.', '1', '2', '3', '.', '1', '2', '3']),
-                literal: Some(Literal::Str("123.123.123".to_string())),
-                line: 0,
-            }],
+        let (result, errors) = scan_tokens("123.123.123");
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].lexeme, "123.123.123");
+        assert_eq!(
+            errors,
+            vec![LexError::MalformedNumber {
+                lexeme: "123.123.123".to_string(),
+                reason: "a number can only have one fractional part",
+                span: Span::new(7, 11, 1, 8),
+            }]
         )
     }
     #[test]
     fn number_with_decimal_but_word() {
-        assert_scanner_results(
-            "123.123ffafaf",
-            vec![Token {
-                token_type: TokenType::IDENTIFIER,
-                lexeme: Some(vec![
-                    '1', '2', '3', '.', '1', '2', '3', 'f', 'f', 'a', 'f', 'a', 'f',
-                ]),
-                line: 0,
-                literal: Some(Literal::Str("123.123ffafaf".to_string())),
-            }],
+        let (result, errors) = scan_tokens("123.123ffafaf");
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].lexeme, "123.123ffafaf");
+        assert_eq!(
+            errors,
+            vec![LexError::MalformedNumber {
+                lexeme: "123.123ffafaf".to_string(),
+                reason: "unexpected characters after the number",
+                span: Span::new(7, 13, 1, 8),
+            }]
         )
     }
     #[test]
     fn unidentified() {
-        let eof_token = vec![Token {
+        let eof_token = [Token {
             token_type: TokenType::EOF,
-            lexeme: None,
+            lexeme: "",
             literal: None,
-            line: 0,
+            span: Span::new(1, 1, 1, 2),
         }];
-        let result = scan_tokens("@".to_string());
-        // should break out of loop when finding unsupported char?
-        assert_eq!(result.first(), eof_token.first());
-        assert_eq!(result.last(), eof_token.last())
+        let (result, errors) = scan_tokens("@");
+        assert_eq!(result, eof_token);
+        assert_eq!(
+            errors,
+            vec![LexError::UnexpectedChar {
+                character: '@',
+                span: Span::new(0, 1, 1, 1),
+            }]
+        );
     }
     #[test]
     fn fun() {
@@ -587,8 +1185,8 @@ mod tests {
             "fun",
             vec![Token {
                 token_type: TokenType::FUN,
-                lexeme: Some(vec!['f', 'u', 'n']),
-                line: 0,
+                lexeme: "fun",
+                span: Span::default(),
                 literal: None,
             }],
         )
@@ -599,8 +1197,8 @@ mod tests {
             "for",
             vec![Token {
                 token_type: TokenType::FOR,
-                lexeme: Some(vec!['f', 'o', 'r']),
-                line: 0,
+                lexeme: "for",
+                span: Span::default(),
                 literal: None,
             }],
         )
@@ -611,8 +1209,8 @@ mod tests {
             "false",
             vec![Token {
                 token_type: TokenType::FALSE,
-                lexeme: Some(vec!['f', 'a', 'l', 's', 'e']),
-                line: 0,
+                lexeme: "false",
+                span: Span::default(),
                 literal: None,
             }],
         )
@@ -624,14 +1222,14 @@ mod tests {
             vec![
                 Token {
                     token_type: TokenType::FALSE,
-                    lexeme: Some(vec!['f', 'a', 'l', 's', 'e']),
-                    line: 0,
+                    lexeme: "false",
+                    span: Span::default(),
                     literal: None,
                 },
                 Token {
                     token_type: TokenType::FUN,
-                    lexeme: Some(vec!['f', 'u', 'n']),
-                    line: 0,
+                    lexeme: "fun",
+                    span: Span::default(),
                     literal: None,
                 },
             ],
@@ -643,9 +1241,9 @@ mod tests {
             "funny",
             vec![Token {
                 token_type: TokenType::IDENTIFIER,
-                lexeme: Some(vec!['f', 'u', 'n', 'n', 'y']),
-                line: 0,
-                literal: Some(Literal::Identifier("funny".to_string())),
+                lexeme: "funny",
+                span: Span::default(),
+                literal: Some(Literal::Identifier("funny")),
             }],
         )
     }
@@ -655,9 +1253,9 @@ mod tests {
             "nile",
             vec![Token {
                 token_type: TokenType::IDENTIFIER,
-                lexeme: Some(vec!['n', 'i', 'l', 'e']),
-                line: 0,
-                literal: Some(Literal::Identifier("nile".to_string())),
+                lexeme: "nile",
+                span: Span::default(),
+                literal: Some(Literal::Identifier("nile")),
             }],
         )
     }
@@ -668,30 +1266,745 @@ mod tests {
             vec![
                 Token {
                     token_type: TokenType::VAR,
-                    lexeme: Some(vec!['v', 'a', 'r']),
-                    line: 0,
+                    lexeme: "var",
+                    span: Span::default(),
                     literal: None,
                 },
                 Token {
                     token_type: TokenType::IDENTIFIER,
-                    lexeme: Some(vec!['h', 'e', 'l', 'l', 'o']),
-                    line: 0,
-                    literal: Some(Literal::Identifier("hello".to_string())),
+                    lexeme: "hello",
+                    span: Span::default(),
+                    literal: Some(Literal::Identifier("hello")),
                 },
-                Token::new_token(TokenType::EQUAL),
+                Token::new(TokenType::EQUAL, "=", None, Span::default()),
                 Token {
                     token_type: TokenType::NUMBER,
-                    lexeme: Some(vec!['2', '.', '1', '2', '1', '2']),
-                    line: 0,
+                    lexeme: "2.1212",
+                    span: Span::default(),
                     literal: Some(Literal::Number(2.1212)),
                 },
                 Token {
                     token_type: TokenType::FUN,
-                    lexeme: Some(vec!['f', 'u', 'n']),
-                    line: 0,
+                    lexeme: "fun",
+                    span: Span::default(),
                     literal: None,
                 },
             ],
         )
     }
+    #[test]
+    fn bang_does_not_swallow_next_char() {
+        assert_scanner_results(
+            "a!b",
+            vec![
+                Token {
+                    token_type: TokenType::IDENTIFIER,
+                    lexeme: "a",
+                    span: Span::default(),
+                    literal: Some(Literal::Identifier("a")),
+                },
+                Token::new(TokenType::BANG, "!", None, Span::default()),
+                Token {
+                    token_type: TokenType::IDENTIFIER,
+                    lexeme: "b",
+                    span: Span::default(),
+                    literal: Some(Literal::Identifier("b")),
+                },
+            ],
+        )
+    }
+    #[test]
+    fn equal_without_whitespace() {
+        assert_scanner_results(
+            "x=1",
+            vec![
+                Token {
+                    token_type: TokenType::IDENTIFIER,
+                    lexeme: "x",
+                    span: Span::default(),
+                    literal: Some(Literal::Identifier("x")),
+                },
+                Token::new(TokenType::EQUAL, "=", None, Span::default()),
+                Token {
+                    token_type: TokenType::NUMBER,
+                    lexeme: "1",
+                    span: Span::default(),
+                    literal: Some(Literal::Number(1_f64)),
+                },
+            ],
+        )
+    }
+    #[test]
+    fn less_without_whitespace() {
+        assert_scanner_results(
+            "1<2",
+            vec![
+                Token {
+                    token_type: TokenType::NUMBER,
+                    lexeme: "1",
+                    span: Span::default(),
+                    literal: Some(Literal::Number(1_f64)),
+                },
+                Token::new(TokenType::LESS, "<", None, Span::default()),
+                Token {
+                    token_type: TokenType::NUMBER,
+                    lexeme: "2",
+                    span: Span::default(),
+                    literal: Some(Literal::Number(2_f64)),
+                },
+            ],
+        )
+    }
+    #[test]
+    fn two_char_operators_without_whitespace() {
+        assert_scanner_results(
+            "!=<=",
+            vec![
+                Token::new(TokenType::BangEqual, "!=", None, Span::default()),
+                Token::new(TokenType::LessEqual, "<=", None, Span::default()),
+            ],
+        )
+    }
+    #[test]
+    fn spans_on_single_line() {
+        assert_eq!(
+            spans_of("var x = 10;"),
+            vec![
+                (TokenType::VAR, Span::new(0, 3, 1, 1)),
+                (TokenType::IDENTIFIER, Span::new(4, 5, 1, 5)),
+                (TokenType::EQUAL, Span::new(6, 7, 1, 7)),
+                (TokenType::NUMBER, Span::new(8, 10, 1, 9)),
+                (TokenType::SemiColon, Span::new(10, 11, 1, 11)),
+                (TokenType::EOF, Span::new(11, 11, 1, 12)),
+            ]
+        )
+    }
+    #[test]
+    fn spans_after_comment() {
+        assert_eq!(
+            spans_of("// comment\n  >="),
+            vec![
+                (TokenType::GreatEqual, Span::new(13, 15, 2, 3)),
+                (TokenType::EOF, Span::new(15, 15, 2, 5)),
+            ]
+        )
+    }
+    #[test]
+    fn spans_for_multi_line_string() {
+        assert_eq!(
+            spans_of("\"a\nb\" 1"),
+            vec![
+                (TokenType::STRING, Span::new(0, 5, 1, 1)),
+                (TokenType::NUMBER, Span::new(6, 7, 2, 4)),
+                (TokenType::EOF, Span::new(7, 7, 2, 5)),
+            ]
+        )
+    }
+    #[test]
+    fn span_line_text() {
+        let source = "var a = 1;\r\nprint a;\nvar b;";
+        let (tokens, _) = scan_tokens(source);
+        let print = tokens
+            .iter()
+            .find(|token| token.token_type == TokenType::PRINT)
+            .unwrap();
+        assert_eq!(print.span.text(source), "print");
+        assert_eq!(print.span.line_text(source), "print a;");
+        assert_eq!(tokens[0].span.line_text(source), "var a = 1;");
+        assert_eq!(tokens.last().unwrap().span.line_text(source), "var b;");
+    }
+    #[test]
+    fn span_end_position() {
+        let source = "x \"a\nbc\" y";
+        let (tokens, _) = scan_tokens(source);
+        assert_eq!(tokens[0].span.end_position(source), (1, 2));
+        assert_eq!(tokens[1].span.end_position(source), (2, 4));
+        assert_eq!(tokens[2].span.end_position(source), (2, 6));
+    }
+    #[test]
+    fn identifier_stops_at_punctuation() {
+        assert_eq!(
+            token_types_of("print(x);"),
+            vec![
+                TokenType::PRINT,
+                TokenType::LeftParen,
+                TokenType::IDENTIFIER,
+                TokenType::RightParen,
+                TokenType::SemiColon,
+                TokenType::EOF,
+            ]
+        )
+    }
+    #[test]
+    fn property_access() {
+        assert_eq!(
+            token_types_of("foo.bar"),
+            vec![
+                TokenType::IDENTIFIER,
+                TokenType::DOT,
+                TokenType::IDENTIFIER,
+                TokenType::EOF,
+            ]
+        )
+    }
+    #[test]
+    fn identifier_with_underscores_and_digits() {
+        assert_scanner_results(
+            "_foo_1",
+            vec![Token {
+                token_type: TokenType::IDENTIFIER,
+                lexeme: "_foo_1",
+                span: Span::default(),
+                literal: Some(Literal::Identifier("_foo_1")),
+            }],
+        )
+    }
+    #[test]
+    fn number_followed_by_semicolon() {
+        assert_scanner_results(
+            "123;",
+            vec![
+                Token {
+                    token_type: TokenType::NUMBER,
+                    lexeme: "123",
+                    literal: Some(Literal::Number(123_f64)),
+                    span: Span::default(),
+                },
+                Token::new(TokenType::SemiColon, ";", None, Span::default()),
+            ],
+        )
+    }
+    #[test]
+    fn number_with_trailing_dot() {
+        assert_scanner_results(
+            "123.",
+            vec![
+                Token {
+                    token_type: TokenType::NUMBER,
+                    lexeme: "123",
+                    literal: Some(Literal::Number(123_f64)),
+                    span: Span::default(),
+                },
+                Token::new(TokenType::DOT, ".", None, Span::default()),
+            ],
+        )
+    }
+    #[test]
+    fn function_without_whitespace() {
+        assert_eq!(
+            token_types_of("fun add(a,b) return a+b;"),
+            vec![
+                TokenType::FUN,
+                TokenType::IDENTIFIER,
+                TokenType::LeftParen,
+                TokenType::IDENTIFIER,
+                TokenType::Comma,
+                TokenType::IDENTIFIER,
+                TokenType::RightParen,
+                TokenType::RETURN,
+                TokenType::IDENTIFIER,
+                TokenType::PLUS,
+                TokenType::IDENTIFIER,
+                TokenType::SemiColon,
+                TokenType::EOF,
+            ]
+        )
+    }
+    #[test]
+    fn every_keyword() {
+        for (word, token_type) in KEYWORDS {
+            assert_eq!(token_types_of(word), vec![*token_type, TokenType::EOF]);
+        }
+    }
+    #[test]
+    fn keyword_prefixes_and_extensions_are_identifiers() {
+        for word in [
+            "a", "an", "f", "fo", "fu", "classy", "orchid", "t", "whiles",
+        ] {
+            assert_eq!(
+                token_types_of(word),
+                vec![TokenType::IDENTIFIER, TokenType::EOF],
+                "{}",
+                word
+            );
+        }
+    }
+    #[test]
+    fn lexemes_borrow_from_source() {
+        let source = String::from("var greeting = \"hi\";");
+        let source_range = source.as_bytes().as_ptr_range();
+        for token in scan_tokens(&source).0 {
+            assert!(source_range.contains(&token.lexeme.as_ptr()) || token.lexeme.is_empty());
+            if let Some(Literal::Str(value)) = token.literal {
+                assert!(matches!(value, Cow::Borrowed("hi")));
+            }
+        }
+    }
+    #[test]
+    fn unterminated_string() {
+        let (result, errors) = scan_tokens("print \"hello\n");
+        assert_eq!(result.len(), 2);
+        assert_eq!(
+            errors,
+            vec![LexError::UnterminatedString {
+                span: Span::new(6, 13, 1, 7),
+            }]
+        );
+        assert_eq!(errors[0].to_string(), "Unterminated string");
+    }
+    #[test]
+    fn scanning_continues_after_errors() {
+        let (result, errors) = scan_tokens("a @ b # 1x");
+        assert_eq!(
+            result.iter().map(|token| token.lexeme).collect::<Vec<_>>(),
+            vec!["a", "b", "1x", ""]
+        );
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "Unexpected character '@'",
+                "Unexpected character '#'",
+                "Malformed number literal '1x': unexpected characters after the number",
+            ]
+        );
+    }
+    #[test]
+    fn single_level_right_bracket() {
+        assert_scanner_results(
+            "]",
+            vec![Token::new(
+                TokenType::RightBracket,
+                "]",
+                None,
+                Span::default(),
+            )],
+        );
+    }
+    #[test]
+    fn braces() {
+        assert_scanner_results(
+            "{}",
+            vec![
+                Token::new(TokenType::LeftBrace, "{", None, Span::default()),
+                Token::new(TokenType::RightBrace, "}", None, Span::default()),
+            ],
+        );
+    }
+    #[test]
+    fn brackets_inside_block() {
+        assert_eq!(
+            token_types_of("{[]}"),
+            vec![
+                TokenType::LeftBrace,
+                TokenType::LeftBracket,
+                TokenType::RightBracket,
+                TokenType::RightBrace,
+                TokenType::EOF,
+            ]
+        )
+    }
+    #[test]
+    fn function_with_block_body() {
+        assert_eq!(
+            token_types_of("fun f(a,b){return a+b;}"),
+            vec![
+                TokenType::FUN,
+                TokenType::IDENTIFIER,
+                TokenType::LeftParen,
+                TokenType::IDENTIFIER,
+                TokenType::Comma,
+                TokenType::IDENTIFIER,
+                TokenType::RightParen,
+                TokenType::LeftBrace,
+                TokenType::RETURN,
+                TokenType::IDENTIFIER,
+                TokenType::PLUS,
+                TokenType::IDENTIFIER,
+                TokenType::SemiColon,
+                TokenType::RightBrace,
+                TokenType::EOF,
+            ]
+        )
+    }
+    #[test]
+    fn whitespace_is_not_an_error() {
+        let (result, errors) = scan_tokens(" \t\r\n\u{A0}\u{3000}\u{FEFF}\n");
+        assert_eq!(errors, vec![]);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].span.line, 3);
+    }
+    #[test]
+    fn crlf_line_endings() {
+        assert_eq!(
+            spans_of("var\r\nx\r\n\r\n  ;"),
+            vec![
+                (TokenType::VAR, Span::new(0, 3, 1, 1)),
+                (TokenType::IDENTIFIER, Span::new(5, 6, 2, 1)),
+                (TokenType::SemiColon, Span::new(12, 13, 4, 3)),
+                (TokenType::EOF, Span::new(13, 13, 4, 4)),
+            ]
+        )
+    }
+    #[test]
+    fn lone_carriage_return_is_a_column() {
+        assert_eq!(
+            spans_of("a\rb"),
+            vec![
+                (TokenType::IDENTIFIER, Span::new(0, 1, 1, 1)),
+                (TokenType::IDENTIFIER, Span::new(2, 3, 1, 3)),
+                (TokenType::EOF, Span::new(3, 3, 1, 4)),
+            ]
+        )
+    }
+    #[test]
+    fn unicode_spaces_count_as_one_column() {
+        assert_eq!(
+            spans_of("a\u{3000}b"),
+            vec![
+                (TokenType::IDENTIFIER, Span::new(0, 1, 1, 1)),
+                (TokenType::IDENTIFIER, Span::new(4, 5, 1, 3)),
+                (TokenType::EOF, Span::new(5, 5, 1, 4)),
+            ]
+        )
+    }
+    #[test]
+    fn string_escapes() {
+        let (result, errors) = scan_tokens(r#""a\"b\\c\nd\te\rf\0g\u{1F600}\u{e9}""#);
+        assert_eq!(errors, vec![]);
+        assert_eq!(result[0].lexeme, r#""a\"b\\c\nd\te\rf\0g\u{1F600}\u{e9}""#);
+        assert_eq!(
+            result[0].literal,
+            Some(Literal::Str(Cow::Owned(
+                "a\"b\\c\nd\te\rf\0g\u{1F600}\u{e9}".to_string()
+            )))
+        );
+    }
+    #[test]
+    fn string_without_escapes_is_borrowed() {
+        let (result, _) = scan_tokens(r#""plain""#);
+        assert!(matches!(
+            result[0].literal,
+            Some(Literal::Str(Cow::Borrowed("plain")))
+        ));
+    }
+    #[test]
+    fn invalid_escapes() {
+        for (source, sequence, span) in [
+            (r#""a\qb""#, r"\q", Span::new(2, 4, 1, 3)),
+            (r#""\u{110000}""#, r"\u{110000}", Span::new(1, 11, 1, 2)),
+            (r#""\u{D800}""#, r"\u{D800}", Span::new(1, 9, 1, 2)),
+            (r#""\ué""#, r"\u", Span::new(1, 3, 1, 2)),
+            (r#""\u{}""#, r"\u{}", Span::new(1, 5, 1, 2)),
+        ] {
+            let (result, errors) = scan_tokens(source);
+            assert_eq!(result[0].token_type, TokenType::STRING, "{}", source);
+            assert_eq!(result.len(), 2, "{}", source);
+            assert_eq!(
+                errors,
+                vec![LexError::InvalidEscape {
+                    sequence: sequence.to_string(),
+                    span,
+                }],
+                "{}",
+                source
+            );
+        }
+    }
+    #[test]
+    fn invalid_escape_does_not_end_the_string() {
+        let (result, errors) = scan_tokens(r#""\q" + 1"#);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            result
+                .iter()
+                .map(|token| token.token_type)
+                .collect::<Vec<_>>(),
+            vec![
+                TokenType::STRING,
+                TokenType::PLUS,
+                TokenType::NUMBER,
+                TokenType::EOF
+            ]
+        );
+        // the bad escape is kept as written
+        assert_eq!(result[0].literal, Some(Literal::Str(Cow::Borrowed(r"\q"))));
+    }
+    #[test]
+    fn escaped_quote_at_end_is_unterminated() {
+        let (_, errors) = scan_tokens(r#""abc\""#);
+        assert_eq!(
+            errors,
+            vec![LexError::UnterminatedString {
+                span: Span::new(0, 6, 1, 1),
+            }]
+        );
+    }
+    #[test]
+    fn multi_line_string_keeps_line_count() {
+        let source = "\"one\ntwo\\n\nthree\" x";
+        let (result, errors) = scan_tokens(source);
+        assert_eq!(errors, vec![]);
+        assert_eq!(
+            result[0].literal,
+            Some(Literal::Str("one\ntwo\n\nthree".into()))
+        );
+        assert_eq!(result[0].span.line, 1);
+        assert_eq!((result[1].span.line, result[1].span.column), (3, 8));
+    }
+    #[test]
+    fn extended_number_literals() {
+        for (source, value) in [
+            ("0x1F", 31_f64),
+            ("0XfF", 255_f64),
+            ("0b1010", 10_f64),
+            ("0B1", 1_f64),
+            ("1e10", 1e10),
+            ("2.5E-3", 2.5e-3),
+            ("7e+2", 700_f64),
+            ("1_000_000", 1_000_000_f64),
+            ("1_0.2_5e1_0", 10.25e10),
+            ("0x_ff_ff", 65_535_f64),
+            ("0", 0_f64),
+        ] {
+            let (result, errors) = scan_tokens(source);
+            assert_eq!(errors, vec![], "{}", source);
+            assert_eq!(result[0].token_type, TokenType::NUMBER, "{}", source);
+            assert_eq!(result[0].lexeme, source);
+            assert_eq!(
+                result[0].literal,
+                Some(Literal::Number(value)),
+                "{}",
+                source
+            );
+        }
+    }
+    #[test]
+    fn malformed_number_literals() {
+        for (source, reason, span) in [
+            (
+                "0x",
+                "expected hex digits after '0x'",
+                Span::new(0, 2, 1, 1),
+            ),
+            (
+                "0b_",
+                "expected binary digits after '0b'",
+                Span::new(0, 3, 1, 1),
+            ),
+            (
+                "0b102",
+                "invalid digit for a binary literal",
+                Span::new(4, 5, 1, 5),
+            ),
+            (
+                "0x1G",
+                "unexpected characters after the number",
+                Span::new(3, 4, 1, 4),
+            ),
+            (
+                "0x1.5",
+                "hex and binary literals cannot have a fractional part",
+                Span::new(3, 5, 1, 4),
+            ),
+            (
+                "1e",
+                "expected digits in the exponent",
+                Span::new(1, 2, 1, 2),
+            ),
+            (
+                "1e+x",
+                "expected digits in the exponent",
+                Span::new(1, 3, 1, 2),
+            ),
+            (
+                "1_",
+                "digit separators must be followed by a digit",
+                Span::new(1, 2, 1, 2),
+            ),
+            (
+                "1.5_",
+                "digit separators must be followed by a digit",
+                Span::new(3, 4, 1, 4),
+            ),
+        ] {
+            let (result, errors) = scan_tokens(source);
+            assert_eq!(result[0].token_type, TokenType::NUMBER, "{}", source);
+            assert_eq!(result.len(), 2, "{}", source);
+            assert_eq!(
+                errors,
+                vec![LexError::MalformedNumber {
+                    lexeme: source.to_string(),
+                    reason,
+                    span,
+                }],
+                "{}",
+                source
+            );
+        }
+    }
+    #[test]
+    fn dot_before_a_name_is_not_a_fraction() {
+        assert_eq!(
+            token_types_of("1.x"),
+            vec![
+                TokenType::NUMBER,
+                TokenType::DOT,
+                TokenType::IDENTIFIER,
+                TokenType::EOF
+            ]
+        );
+        assert_eq!(
+            token_types_of("1._5"),
+            vec![
+                TokenType::NUMBER,
+                TokenType::DOT,
+                TokenType::IDENTIFIER,
+                TokenType::EOF
+            ]
+        )
+    }
+    #[test]
+    fn names_starting_like_infinity_are_identifiers() {
+        assert_eq!(
+            token_types_of("inf infinity"),
+            vec![TokenType::IDENTIFIER, TokenType::IDENTIFIER, TokenType::EOF]
+        )
+    }
+    #[test]
+    fn block_comments() {
+        let (result, errors) = scan_tokens("a /* one\n two */ b /**/ c /* * / */");
+        assert_eq!(errors, vec![]);
+        assert_eq!(
+            result
+                .iter()
+                .map(|token| (token.lexeme, token.span.line))
+                .collect::<Vec<_>>(),
+            vec![("a", 1), ("b", 2), ("c", 2), ("", 2)]
+        );
+    }
+    #[test]
+    fn nested_block_comments() {
+        assert_eq!(
+            token_types_of("/* outer /* inner */ still outer */ 1 / 2 // done"),
+            vec![
+                TokenType::NUMBER,
+                TokenType::SLASH,
+                TokenType::NUMBER,
+                TokenType::EOF
+            ]
+        );
+    }
+    #[test]
+    fn unterminated_block_comment() {
+        let (result, errors) = scan_tokens("x\n  /* open /* nested */\n");
+        assert_eq!(result.len(), 2);
+        assert_eq!(
+            errors,
+            vec![LexError::UnterminatedBlockComment {
+                span: Span::new(4, 6, 2, 3),
+            }]
+        );
+    }
+    #[test]
+    fn line_comment_at_end_of_source() {
+        let (result, errors) = scan_tokens("x // no newline after this");
+        assert_eq!(errors, vec![]);
+        assert_eq!(result.len(), 2);
+    }
+    #[test]
+    fn scanner_is_lazy() {
+        let source = "var x = 1;".repeat(10_000);
+        let mut scanner = Scanner::new(&source);
+        assert_eq!(scanner.next().unwrap().unwrap().token_type, TokenType::VAR);
+        assert_eq!(
+            scanner.next().unwrap().unwrap().token_type,
+            TokenType::IDENTIFIER
+        );
+        // nothing past the second token has been looked at
+        assert_eq!(scanner.current, 5);
+    }
+    #[test]
+    fn scanner_yields_errors_in_order() {
+        let results: Vec<Result<TokenType, String>> = Scanner::new("a @ /* c */ 1x b")
+            .map(|result| {
+                result
+                    .map(|token| token.token_type)
+                    .map_err(|error| error.code().to_string())
+            })
+            .collect();
+        assert_eq!(
+            results,
+            vec![
+                Ok(TokenType::IDENTIFIER),
+                Err("E0001".to_string()),
+                Ok(TokenType::NUMBER),
+                Err("E0003".to_string()),
+                Ok(TokenType::IDENTIFIER),
+                Ok(TokenType::EOF),
+            ]
+        );
+    }
+    #[test]
+    fn scanner_is_fused_after_eof() {
+        let mut scanner = Scanner::new("x");
+        assert!(scanner.next().is_some());
+        assert!(scanner.next().is_some());
+        assert!(scanner.next().is_none());
+        assert!(scanner.next().is_none());
+    }
+    #[test]
+    fn incomplete_input_errors() {
+        let incomplete = |source| Scanner::new(source).any(|r| r.is_err_and(|e| e.is_incomplete()));
+        assert!(incomplete("print \"abc"));
+        assert!(incomplete("1 /* open /* nested */"));
+        assert!(!incomplete("print \"abc\";"));
+        assert!(!incomplete("@"));
+    }
+    #[test]
+    fn unicode_identifiers() {
+        let source = "var größe = число + 変数_2 + _é;";
+        let (tokens, errors) = scan_tokens(source);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let names: Vec<&str> = tokens
+            .iter()
+            .filter(|token| token.token_type == TokenType::IDENTIFIER)
+            .map(|token| token.lexeme)
+            .collect();
+        assert_eq!(names, vec!["größe", "число", "変数_2", "_é"]);
+    }
+    #[test]
+    fn identifiers_keep_combining_marks() {
+        // 'e' followed by U+0301 COMBINING ACUTE ACCENT is one identifier,
+        // distinct from the precomposed 'é'
+        let (tokens, errors) = scan_tokens("cafe\u{301} café");
+        assert!(errors.is_empty());
+        assert_eq!(tokens[0].lexeme, "cafe\u{301}");
+        assert_eq!(tokens[1].lexeme, "café");
+        assert_ne!(tokens[0].literal, tokens[1].literal);
+    }
+    #[test]
+    fn symbols_are_not_identifiers() {
+        let (_, errors) = scan_tokens("a€b 😀");
+        assert_eq!(
+            token_types_of("a€b 😀"),
+            vec![TokenType::IDENTIFIER, TokenType::IDENTIFIER, TokenType::EOF]
+        );
+        let unexpected: Vec<char> = errors
+            .iter()
+            .map(|error| match error {
+                LexError::UnexpectedChar { character, .. } => *character,
+                other => panic!("unexpected error {:?}", other),
+            })
+            .collect();
+        assert_eq!(unexpected, vec!['€', '😀']);
+    }
+    #[test]
+    fn combining_mark_cannot_start_identifier() {
+        let (tokens, errors) = scan_tokens("\u{301}a");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(tokens[0].lexeme, "a");
+    }
 }
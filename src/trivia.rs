@@ -0,0 +1,286 @@
+use crate::tokens::{LexError, Scanner, Span, Step, Token};
+
+/// Source text between tokens that the parser never sees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    // spaces, tabs and other non line breaking whitespace
+    Whitespace,
+    // "\n" or "\r\n"
+    Newline,
+    // from // up to, but not including, the line break
+    LineComment,
+    // /* ... */ including nested comments
+    BlockComment,
+    // text that failed to scan ie '@' or an unterminated string, the matching
+    // LexError is returned alongside the tokens
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia<'src> {
+    pub kind: TriviaKind,
+    pub text: &'src str,
+    pub span: Span,
+}
+
+/// A token together with the trivia around it.
+///
+/// Trailing trivia is everything after the token up to, but not including,
+/// the next line break. Everything else, line breaks included, leads the
+/// following token, so the final EOF token carries whatever ends the file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LosslessToken<'src> {
+    pub leading: Vec<Trivia<'src>>,
+    pub token: Token<'src>,
+    pub trailing: Vec<Trivia<'src>>,
+}
+
+impl LosslessToken<'_> {
+    /// The exact source text of the token and its trivia.
+    pub fn full_text(&self) -> String {
+        let mut text = String::new();
+        for trivia in &self.leading {
+            text.push_str(trivia.text);
+        }
+        text.push_str(self.token.lexeme);
+        for trivia in &self.trailing {
+            text.push_str(trivia.text);
+        }
+        text
+    }
+}
+
+/// Scans like `scan_tokens` but keeps whitespace, comments and erroneous text
+/// attached to the tokens, for tools such as formatters and highlighters that
+/// need to reproduce the source.
+///
+/// Concatenating the `full_text` of every returned token gives back the
+/// input byte for byte.
+pub fn scan_tokens_lossless(source: &str) -> (Vec<LosslessToken<'_>>, Vec<LexError>) {
+    let mut scanner = Scanner::new(source);
+    let mut tokens: Vec<LosslessToken> = Vec::new();
+    let mut errors: Vec<LexError> = Vec::new();
+    let mut pending: Vec<Trivia> = Vec::new();
+    // trivia after the last token stays trailing until the line ends
+    let mut on_token_line = false;
+
+    while let Some(step) = scanner.step() {
+        let trivia_kind = match step {
+            Step::Token(token) => {
+                tokens.push(LosslessToken {
+                    leading: std::mem::take(&mut pending),
+                    token,
+                    trailing: Vec::new(),
+                });
+                on_token_line = true;
+                continue;
+            }
+            Step::Trivia(kind) => kind,
+            // the token was kept, so there is no text to skip
+            Step::TokenError(error) => {
+                errors.push(error);
+                continue;
+            }
+            Step::Error(error) => {
+                errors.push(error);
+                TriviaKind::Skipped
+            }
+        };
+
+        let trivia = Trivia {
+            kind: trivia_kind,
+            text: scanner.lexeme(),
+            span: scanner.current_span(),
+        };
+        // a multi-line block comment or skipped string ends the line too
+        let breaks_line = trivia_kind == TriviaKind::Newline || trivia.text.contains('\n');
+        match tokens.last_mut() {
+            Some(previous) if on_token_line && !breaks_line => previous.trailing.push(trivia),
+            _ => {
+                on_token_line = false;
+                pending.push(trivia);
+            }
+        }
+    }
+
+    (tokens, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_rng::Rng;
+    use crate::tokens::{scan_tokens, TokenType};
+
+    fn kinds(trivia: &[Trivia]) -> Vec<(TriviaKind, String)> {
+        trivia
+            .iter()
+            .map(|trivia| (trivia.kind, trivia.text.to_string()))
+            .collect()
+    }
+
+    fn round_trip(source: &str) -> String {
+        scan_tokens_lossless(source)
+            .0
+            .iter()
+            .map(LosslessToken::full_text)
+            .collect()
+    }
+
+    #[test]
+    fn attaches_leading_and_trailing_trivia() {
+        let (tokens, errors) = scan_tokens_lossless("a  // note\r\n\t/* b */ b;\n");
+        assert_eq!(errors, vec![]);
+        assert_eq!(tokens.len(), 4);
+
+        assert_eq!(kinds(&tokens[0].leading), vec![]);
+        assert_eq!(
+            kinds(&tokens[0].trailing),
+            vec![
+                (TriviaKind::Whitespace, "  ".to_string()),
+                (TriviaKind::LineComment, "// note".to_string()),
+            ]
+        );
+        assert_eq!(
+            kinds(&tokens[1].leading),
+            vec![
+                (TriviaKind::Newline, "\r\n".to_string()),
+                (TriviaKind::Whitespace, "\t".to_string()),
+                (TriviaKind::BlockComment, "/* b */".to_string()),
+                (TriviaKind::Whitespace, " ".to_string()),
+            ]
+        );
+        assert_eq!(tokens[1].token.lexeme, "b");
+        assert_eq!(kinds(&tokens[2].trailing), vec![]);
+        assert_eq!(tokens[3].token.token_type, TokenType::EOF);
+        assert_eq!(
+            kinds(&tokens[3].leading),
+            vec![(TriviaKind::Newline, "\n".to_string())]
+        );
+    }
+
+    #[test]
+    fn multi_line_comment_leads_the_next_token() {
+        let (tokens, _) = scan_tokens_lossless("a /* one\ntwo */ b");
+        assert_eq!(
+            kinds(&tokens[0].trailing),
+            vec![(TriviaKind::Whitespace, " ".to_string())]
+        );
+        assert_eq!(
+            kinds(&tokens[1].leading),
+            vec![
+                (TriviaKind::BlockComment, "/* one\ntwo */".to_string()),
+                (TriviaKind::Whitespace, " ".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_become_skipped_trivia() {
+        let (tokens, errors) = scan_tokens_lossless("a @ \"open");
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            kinds(&tokens[0].trailing),
+            vec![
+                (TriviaKind::Whitespace, " ".to_string()),
+                (TriviaKind::Skipped, "@".to_string()),
+                (TriviaKind::Whitespace, " ".to_string()),
+                (TriviaKind::Skipped, "\"open".to_string()),
+            ]
+        );
+    }
+
+    const FRAGMENTS: &[&str] = &[
+        "var",
+        "x",
+        "_name1",
+        "fun",
+        "print",
+        "123",
+        "4.5",
+        "0x1F",
+        "1e3",
+        "1_0",
+        "(",
+        ")",
+        "{",
+        "}",
+        "[",
+        "]",
+        ",",
+        ".",
+        ";",
+        "+",
+        "-",
+        "*",
+        "/",
+        "!",
+        "!=",
+        "=",
+        "==",
+        "<",
+        "<=",
+        ">",
+        ">=",
+        " ",
+        "  ",
+        "\t",
+        "\n",
+        "\r\n",
+        "\r",
+        "\u{A0}",
+        "\u{FEFF}",
+        "// line comment",
+        "/* block */",
+        "/* outer /* inner */ */",
+        "/* open",
+        "\"str\"",
+        "\"esc\\n\\\"\"",
+        "\"multi\nline\"",
+        "\"open",
+        "\"bad\\q\"",
+        "@",
+        "#",
+        "é",
+        "1x",
+        "1.2.3",
+        "0b12",
+        "*/",
+    ];
+
+    #[test]
+    fn round_trips_random_sources() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..2000 {
+            let length = rng.next() % 24;
+            let source: String = (0..length).map(|_| rng.pick(FRAGMENTS)).collect();
+
+            let (lossless, lossless_errors) = scan_tokens_lossless(&source);
+            assert_eq!(round_trip(&source), source);
+
+            // the lossless mode sees the same tokens and errors as scan_tokens
+            let (tokens, errors) = scan_tokens(&source);
+            assert_eq!(
+                lossless.into_iter().map(|t| t.token).collect::<Vec<_>>(),
+                tokens,
+                "{:?}",
+                source
+            );
+            assert_eq!(lossless_errors, errors, "{:?}", source);
+        }
+    }
+
+    #[test]
+    fn round_trips_random_characters() {
+        let alphabet = [
+            "a", "1", "_", ".", "/", "*", "\"", "\\", "\n", "\r", " ", "\t", "=", "!", "@", "x",
+            "u", "{", "}", "e", "0", "b", "é", "\u{3000}",
+        ];
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..2000 {
+            let length = rng.next() % 40;
+            let source: String = (0..length).map(|_| rng.pick(&alphabet)).collect();
+            assert_eq!(round_trip(&source), source);
+        }
+    }
+}